@@ -2,7 +2,7 @@
 use anyhow::{anyhow, Context};
 use chrono::{DateTime, Utc};
 
-use mehsh_common::config::ConfigAnalysis;
+use mehsh_common::config::{ConfigAnalysis, ConfigServer};
 use std::process::{ExitStatus, Stdio};
 use tokio::fs::OpenOptions;
 use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
@@ -36,7 +36,11 @@ impl ExecuteAnalysisCommandHandler {
         ::tokio::spawn(async move {
             loop {
                 match Self::execute(config_analysis.clone(), &mut notify_recv).await {
-                    Ok(_) => { println!("WARNING, ExecuteAnalysisCommandHandler::execute finished, should never happen"); },
+                    Ok(_) => {
+                        // the analysis was removed from the config.
+                        println!("analysis {} stopped", &config_analysis.name);
+                        break;
+                    },
                     Err(e) => { println!("WARNING, ExecuteAnalysisCommandHandler::execute finished with error, should never happen: {}", e); },
                 }
             }
@@ -55,14 +59,19 @@ impl ExecuteAnalysisCommandHandler {
         let (execute_sender, mut execute_receiver) =
             ::tokio::sync::mpsc::unbounded_channel::<ExecuteMsg>();
 
-        let mut command_execution_context = None;
+        let mut command_execution_context: Option<CommandExecutionContext> = None;
 
         loop {
             ::tokio::select! {
                 // called when we need to run the command.
                 notify = notify_recv.recv() => {
                     if notify.is_none() {
-                        continue;
+                        // the handler was dropped, stop a running command as well.
+                        if let Some(context) = command_execution_context {
+                            context.jh.abort();
+                        }
+
+                        return Ok(());
                     }
 
                     if command_execution_context.is_some() {
@@ -175,17 +184,66 @@ async fn write_report_file(
 }
 
 fn get_command_with_variables(config: &ConfigAnalysis) -> String {
-    config
+    let mut command = config
         .command
         .clone()
-        .replace("{{server.from.ip}}", &config.from.ip.to_string())
-        .replace("{{server.from.extra1}}", &config.from.extra1.clone().unwrap_or("".to_string()))
-        .replace("{{server.from.extra2}}", &config.from.extra2.clone().unwrap_or("".to_string()))
-        .replace("{{server.from.extra3}}", &config.from.extra3.clone().unwrap_or("".to_string()))
-        .replace("{{server.to.ip}}", &config.to.ip.to_string())
-        .replace("{{server.to.extra1}}", &config.to.extra1.clone().unwrap_or("".to_string()))
-        .replace("{{server.to.extra2}}", &config.to.extra2.clone().unwrap_or("".to_string()))
-        .replace("{{server.to.extra3}}", &config.to.extra3.clone().unwrap_or("".to_string()))
+        .replace("{{server.from.ip}}", &get_interface_ip(&config.from, config.interface.as_deref()))
+        .replace("{{server.to.ip}}", &get_interface_ip(&config.to, config.interface.as_deref()));
+
+    for (name, server) in [("from", &config.from), ("to", &config.to)] {
+        for interface in server.interfaces.keys() {
+            command = command.replace(
+                &format!("{{{{server.{}.interfaces.{}}}}}", name, interface),
+                &get_interface_ip(server, Some(interface)),
+            );
+        }
+
+        // extra1 - extra3 are kept for old configs, they are labels as well.
+        for extra in ["extra1", "extra2", "extra3"] {
+            command = command.replace(
+                &format!("{{{{server.{}.{}}}}}", name, extra),
+                &format!("{{{{server.{}.labels.{}}}}}", name, extra),
+            );
+        }
+
+        command = replace_labels(command, name, server);
+    }
+
+    command
+}
+
+/// replaces `{{server.NAME.labels.LABEL}}`, labels the server does not have are empty.
+fn replace_labels(command: String, name: &str, server: &ConfigServer) -> String {
+    let prefix = format!("{{{{server.{}.labels.", name);
+    let mut buf = String::with_capacity(command.len());
+    let mut rest = command.as_str();
+
+    while let Some(start) = rest.find(&prefix) {
+        let label_start = start + prefix.len();
+        let label_end = match rest[label_start..].find("}}") {
+            Some(end) => label_start + end,
+            None => break,
+        };
+
+        buf.push_str(&rest[..start]);
+        if let Some(value) = server.labels.get(&rest[label_start..label_end]) {
+            buf.push_str(value);
+        }
+        rest = &rest[label_end + 2..];
+    }
+
+    buf.push_str(rest);
+    buf
+}
+
+/// first address of the interface, falls back to the `ip` of the server.
+fn get_interface_ip(server: &ConfigServer, interface: Option<&str>) -> String {
+    interface
+        .and_then(|interface| server.interfaces.get(interface))
+        .and_then(|ips| ips.first())
+        .unwrap_or(&server.ip)
+        .trim_start_matches("v4:")
+        .trim_start_matches("v6:")
         .to_string()
 }
 
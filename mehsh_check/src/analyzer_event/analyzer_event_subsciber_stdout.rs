@@ -1,4 +1,7 @@
-use crate::udp_echo::analyzer_event::{UdpEchoAnalyzerEventDatacenter, UdpEchoAnalyzerEventServer};
+use crate::udp_echo::analyzer_event::{
+    UdpEchoAnalyzerEventDatacenter, UdpEchoAnalyzerEventLabel, UdpEchoAnalyzerEventServer,
+    UdpEchoRollingStats, UdpEchoServerStats,
+};
 use crate::BroadcastEvent;
 use crate::maintenance_mode::MaintenanceMode;
 
@@ -24,6 +27,10 @@ impl AnalyzerEventSubscriverStout {
                     BroadcastEvent::UdpEchoAnalyzerEventDatacenter(e) => {
                         self.on_udp_echo_analyzer_event_datacenter(e).await
                     }
+                    BroadcastEvent::UdpEchoAnalyzerEventLabel(e) => {
+                        self.on_udp_echo_analyzer_event_label(e).await
+                    }
+                    BroadcastEvent::UdpEchoServerStats(e) => self.on_udp_echo_server_stats(e),
                 },
             }
         }
@@ -38,32 +45,127 @@ impl AnalyzerEventSubscriverStout {
 
     pub async fn on_udp_echo_analyzer_event_server(&self, event: UdpEchoAnalyzerEventServer) {
 
-        let loss = event.req_count - event.resp_count;
+        let loss = event.loss;
+        let labels = event
+            .server_to_labels
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join(",");
         println!(
-            "{} server: {}, ip: {}, req: {:?}, resp: {:?}, max_lat: {:?}, min_lat: {:?}, mode: {}, loss: {:?}, {}",
-            event.date_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            "{} server: {}, interface: {}, ip: {}, port: {}, labels: {}, req: {:?}, resp: {:?}, max_lat: {:?}, min_lat: {:?}, avg_lat: {:?}, p50_lat: {:?}, p90_lat: {:?}, p99_lat: {:?}, jitter: {:?}, mode: {}, loss: {:?}, loss_percent: {:.2}, loss_forward: {:?}, loss_return: {:?}, in_order: {}, reordered: {}, duplicates: {}, late: {}, {}, {}",
+            event.date_time.format("%Y-%m-%d %H:%M:%S"),
             format!("{} -> {}", &event.server_from, &event.server_to),
+            event.server_to_interface,
             event.server_to_ip,
+            event.server_to_port,
+            labels,
             event.req_count,
             event.resp_count,
             event.max_latency,
             event.min_latency,
+            event.avg_latency,
+            event.p50_latency,
+            event.p90_latency,
+            event.p99_latency,
+            event.jitter,
             Self::get_mode_info().await,
-            loss, if loss > 0 { "withloss" } else { "withoutloss"}
+            loss,
+            event.loss_percent,
+            event.loss_forward,
+            event.loss_return,
+            event.in_order,
+            event.reordered,
+            event.duplicates,
+            event.late,
+            format_rolling(&event.rolling),
+            if loss > 0 { "withloss" } else { "withoutloss"}
         );
     }
     pub async fn on_udp_echo_analyzer_event_datacenter(&self, event: UdpEchoAnalyzerEventDatacenter) {
-        let loss = event.req_count - event.resp_count;
+        let loss = event.loss;
         println!(
-            "{} datacenter: {}, req: {:?}, resp: {:?}, max_lat: {:?}, min_lat: {:?}, mode: {}, loss: {:?}, {}",
+            "{} datacenter: {}, req: {:?}, resp: {:?}, max_lat: {:?}, min_lat: {:?}, avg_lat: {:?}, p50_lat: {:?}, p90_lat: {:?}, p99_lat: {:?}, jitter: {:?}, mode: {}, loss: {:?}, loss_percent: {:.2}, in_order: {}, reordered: {}, duplicates: {}, late: {}, {}, {}",
             event.date_time.format("%Y-%m-%d %H:%M:%S").to_string(),
             format!("{} -> {}", event.datacenter_from, event.datacenter_to),
             event.req_count,
             event.resp_count,
             event.max_latency,
             event.min_latency,
+            event.avg_latency,
+            event.p50_latency,
+            event.p90_latency,
+            event.p99_latency,
+            event.jitter,
+            Self::get_mode_info().await,
+            loss,
+            event.loss_percent,
+            event.in_order,
+            event.reordered,
+            event.duplicates,
+            event.late,
+            format_rolling(&event.rolling),
+            if loss > 0 { "withloss" } else { "withoutloss"}
+        );
+    }
+
+    pub async fn on_udp_echo_analyzer_event_label(&self, event: UdpEchoAnalyzerEventLabel) {
+        let loss = event.loss;
+        println!(
+            "{} label: {} -> {}={}, req: {:?}, resp: {:?}, max_lat: {:?}, min_lat: {:?}, avg_lat: {:?}, p50_lat: {:?}, p90_lat: {:?}, p99_lat: {:?}, jitter: {:?}, mode: {}, loss: {:?}, loss_percent: {:.2}, {}",
+            event.date_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            event.server_from,
+            event.label,
+            event.value,
+            event.req_count,
+            event.resp_count,
+            event.max_latency,
+            event.min_latency,
+            event.avg_latency,
+            event.p50_latency,
+            event.p90_latency,
+            event.p99_latency,
+            event.jitter,
             Self::get_mode_info().await,
-            loss, if loss > 0 { "withloss" } else { "withoutloss"}
+            loss,
+            event.loss_percent,
+            if loss > 0 { "withloss" } else { "withoutloss"}
+        );
+    }
+
+    pub fn on_udp_echo_server_stats(&self, event: UdpEchoServerStats) {
+        let key_ids = event
+            .key_ids
+            .iter()
+            .map(|(id, count)| format!("{}={}", id, count))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        println!(
+            "{} responder: {}, received: {}, answered: {}, malformed: {}, unauthenticated: {}, rejected: {}, rate_limited: {}, key_ids: {}",
+            event.date_time.format("%Y-%m-%d %H:%M:%S"),
+            event.listener,
+            event.received,
+            event.answered,
+            event.malformed,
+            event.unauthenticated,
+            event.rejected,
+            event.rate_limited,
+            key_ids
         );
     }
 }
+
+fn format_rolling(rolling: &UdpEchoRollingStats) -> String {
+    let percent = |p: Option<f64>| p.map(|p| format!("{:.2}", p)).unwrap_or("None".to_string());
+
+    format!(
+        "loss_percent_1m: {}, loss_percent_5m: {}, loss_percent_15m: {}, avg_lat_1m: {:?}, avg_lat_5m: {:?}, avg_lat_15m: {:?}",
+        percent(rolling.loss_percent_1m),
+        percent(rolling.loss_percent_5m),
+        percent(rolling.loss_percent_15m),
+        rolling.avg_latency_1m,
+        rolling.avg_latency_5m,
+        rolling.avg_latency_15m,
+    )
+}
@@ -1,12 +1,16 @@
 use crate::udp_echo::analyzer_event::UdpEchoAnalyzerEventServer;
 use crate::{BroadcastEvent, ExecuteAnalysisCommandHandler};
 use chrono::{DateTime, Duration, Utc};
-use mehsh_common::config::ConfigAnalysis;
+use mehsh_common::config::{ConfigAnalysis, LossAverage};
 use crate::maintenance_mode::MaintenanceMode;
+use std::collections::HashMap;
+use std::time::Instant;
 
 pub struct AnalyzerEventSubscriberAnalysis {
     do_not_collect_until: DateTime<Utc>,
     config_analysis: ConfigAnalysis,
+    /// since when the loss of a path (interface, ip) is above the thresholds.
+    loss_since: HashMap<(String, String), Instant>,
     broadcast_recv: ::tokio::sync::broadcast::Receiver<BroadcastEvent>,
     execute_analysis_command_handler: ExecuteAnalysisCommandHandler,
 }
@@ -22,6 +26,7 @@ impl AnalyzerEventSubscriberAnalysis {
                 config_analysis.clone(),
             ),
             config_analysis,
+            loss_since: HashMap::new(),
             broadcast_recv,
         }
     }
@@ -49,10 +54,20 @@ impl AnalyzerEventSubscriberAnalysis {
         if event.server_from != self.config_analysis.from.identifier {
             return;
         }
+        if let Some(interface) = &self.config_analysis.interface {
+            if &event.server_to_interface != interface {
+                return;
+            }
+        }
 
-        let loss = event.req_count - event.resp_count;
+        let path = (event.server_to_interface.clone(), event.server_to_ip.clone());
+        if !self.is_above_thresholds(&event) {
+            self.loss_since.remove(&path);
+            return;
+        }
 
-        if (loss as u32) < self.config_analysis.min_loss {
+        let loss_since = *self.loss_since.entry(path).or_insert_with(Instant::now);
+        if loss_since.elapsed() < self.config_analysis.min_loss_duration {
             return;
         }
 
@@ -70,4 +85,28 @@ impl AnalyzerEventSubscriberAnalysis {
 
         self.do_not_collect_until = Utc::now() + Duration::seconds(120);
     }
+
+    fn is_above_thresholds(&self, event: &UdpEchoAnalyzerEventServer) -> bool {
+        if let Some(min_loss) = self.config_analysis.min_loss {
+            if event.loss < min_loss as u64 {
+                return false;
+            }
+        }
+
+        if let Some(min_loss_basis_points) = self.config_analysis.min_loss_basis_points {
+            let loss_percent = match self.config_analysis.min_loss_percent_average {
+                LossAverage::Window => Some(event.loss_percent),
+                LossAverage::OneMinute => event.rolling.loss_percent_1m,
+                LossAverage::FiveMinutes => event.rolling.loss_percent_5m,
+                LossAverage::FifteenMinutes => event.rolling.loss_percent_15m,
+            };
+
+            match loss_percent {
+                Some(loss_percent) if loss_percent * 100.0 >= min_loss_basis_points as f64 => {}
+                _ => return false,
+            }
+        }
+
+        true
+    }
 }
@@ -1,4 +1,7 @@
-use crate::udp_echo::analyzer_event::{UdpEchoAnalyzerEventDatacenter, UdpEchoAnalyzerEventServer};
+use crate::udp_echo::analyzer_event::{
+    UdpEchoAnalyzerEventDatacenter, UdpEchoAnalyzerEventLabel, UdpEchoAnalyzerEventServer, UdpEchoRollingStats,
+    UdpEchoServerStats,
+};
 use crate::BroadcastEvent;
 use anyhow::anyhow;
 use openmetrics_udpserver_lib::{create_package_peak, create_package_sum};
@@ -37,7 +40,19 @@ impl AnalyzerEventSubscriberUdpMetric {
                         }
                     }
                     BroadcastEvent::UdpEchoAnalyzerEventDatacenter(e) => {
-                        self.on_udp_echo_analyzer_event_datacenter(&mut sock, e);
+                        if let Err(e) = self.on_udp_echo_analyzer_event_datacenter(&mut sock, e).await {
+                            eprintln!("mehsh could not send udp metrics: {}", e);
+                        }
+                    }
+                    BroadcastEvent::UdpEchoAnalyzerEventLabel(e) => {
+                        if let Err(e) = self.on_udp_echo_analyzer_event_label(&mut sock, e).await {
+                            eprintln!("mehsh could not send udp metrics: {}", e);
+                        }
+                    }
+                    BroadcastEvent::UdpEchoServerStats(e) => {
+                        if let Err(e) = self.on_udp_echo_server_stats(&mut sock, e).await {
+                            eprintln!("mehsh could not send udp metrics: {}", e);
+                        }
                     }
                 },
             };
@@ -49,13 +64,13 @@ impl AnalyzerEventSubscriberUdpMetric {
         sock: &mut UdpSocket,
         event: UdpEchoAnalyzerEventServer,
     ) -> Result<(), ::anyhow::Error> {
-        let loss = if MaintenanceMode::is_active().await {
-            0
+        let (loss, loss_percent) = if MaintenanceMode::is_active().await {
+            (0, 0.0)
         } else {
-            event.req_count - event.resp_count
+            (event.loss, event.loss_percent)
         };
 
-        let target: SocketAddrV4 = "127.0.0.1:1113".parse()?;
+        let target: SocketAddrV4 = UDPSERVER_ENDPOINT.parse()?;
 
         sock.send_to(
             create_package_peak("mehsh.loss", loss as i32)
@@ -81,14 +96,288 @@ impl AnalyzerEventSubscriberUdpMetric {
             target,
         )
         .await?;
+        sock.send_to(
+            create_package_peak(
+                format!("mehsh.recvloss_percent.{}", &event.server_to),
+                basis_points(loss_percent),
+            )
+            .map_err(|e| anyhow!(e))?
+            .as_slice(),
+            target,
+        )
+        .await?;
+        sock.send_to(
+            create_package_sum(
+                format!(
+                    "mehsh.recvloss_interface.{}.{}",
+                    metric_name_part(&event.server_to_interface),
+                    &event.server_to
+                ),
+                loss as i32,
+            )
+            .map_err(|e| anyhow!(e))?
+            .as_slice(),
+            target,
+        )
+        .await?;
+        sock.send_to(
+            create_package_sum(
+                format!("mehsh.recvloss_ip.{}", metric_name_part(&event.server_to_ip)),
+                loss as i32,
+            )
+            .map_err(|e| anyhow!(e))?
+            .as_slice(),
+            target,
+        )
+        .await?;
+        for (name, count) in [
+            ("recvreordered", event.reordered),
+            ("recvduplicates", event.duplicates),
+            ("recvlate", event.late),
+        ] {
+            sock.send_to(
+                create_package_sum(format!("mehsh.{}.{}", name, &event.server_to), count as i32)
+                    .map_err(|e| anyhow!(e))?
+                    .as_slice(),
+                target,
+            )
+            .await?;
+        }
+        send_latency_metrics(
+            sock,
+            target,
+            &event.server_to,
+            [
+                event.avg_latency,
+                event.p50_latency,
+                event.p90_latency,
+                event.p99_latency,
+                event.jitter,
+            ],
+        )
+        .await?;
+        send_rolling_metrics(sock, target, &event.server_to, &event.rolling).await?;
+        // only known if the target counts the received requests.
+        if let (Some(loss_forward), Some(loss_return)) = (event.loss_forward, event.loss_return) {
+            let (loss_forward, loss_return) = match MaintenanceMode::is_active().await {
+                true => (0, 0),
+                false => (loss_forward, loss_return),
+            };
+
+            for (name, loss) in [("recvloss_forward", loss_forward), ("recvloss_return", loss_return)] {
+                sock.send_to(
+                    create_package_sum(format!("mehsh.{}.{}", name, &event.server_to), loss as i32)
+                        .map_err(|e| anyhow!(e))?
+                        .as_slice(),
+                    target,
+                )
+                .await?;
+            }
+        }
+        for (label, value) in &event.server_to_labels {
+            sock.send_to(
+                create_package_sum(
+                    format!("mehsh.recvloss_label.{}.{}", label, metric_name_part(value)),
+                    loss as i32,
+                )
+                .map_err(|e| anyhow!(e))?
+                .as_slice(),
+                target,
+            )
+            .await?;
+        }
 
         Ok(())
     }
 
-    pub fn on_udp_echo_analyzer_event_datacenter(
+    pub async fn on_udp_echo_server_stats(
         &self,
-        _sock: &mut UdpSocket,
-        _event: UdpEchoAnalyzerEventDatacenter,
-    ) {
+        sock: &mut UdpSocket,
+        event: UdpEchoServerStats,
+    ) -> Result<(), ::anyhow::Error> {
+        let target: SocketAddrV4 = UDPSERVER_ENDPOINT.parse()?;
+
+        for (name, count) in [
+            ("received", event.received),
+            ("malformed", event.malformed),
+            ("unauthenticated", event.unauthenticated),
+            ("rejected", event.rejected),
+            ("rate_limited", event.rate_limited),
+        ] {
+            sock.send_to(
+                create_package_sum(format!("mehsh.responder.{}", name), count as i32)
+                    .map_err(|e| anyhow!(e))?
+                    .as_slice(),
+                target,
+            )
+            .await?;
+        }
+
+        for (key_id, count) in &event.key_ids {
+            sock.send_to(
+                create_package_sum(format!("mehsh.responder.key_id.{}", key_id), *count as i32)
+                    .map_err(|e| anyhow!(e))?
+                    .as_slice(),
+                target,
+            )
+            .await?;
+        }
+
+        Ok(())
     }
+
+    pub async fn on_udp_echo_analyzer_event_datacenter(
+        &self,
+        sock: &mut UdpSocket,
+        event: UdpEchoAnalyzerEventDatacenter,
+    ) -> Result<(), ::anyhow::Error> {
+        let target: SocketAddrV4 = UDPSERVER_ENDPOINT.parse()?;
+        let datacenter = format!("datacenter.{}", metric_name_part(&event.datacenter_to));
+
+        let (loss, loss_percent) = match MaintenanceMode::is_active().await {
+            true => (0, 0.0),
+            false => (event.loss, event.loss_percent),
+        };
+        sock.send_to(
+            create_package_sum(format!("mehsh.recvloss.{}", datacenter), loss as i32)
+                .map_err(|e| anyhow!(e))?
+                .as_slice(),
+            target,
+        )
+        .await?;
+        sock.send_to(
+            create_package_peak(format!("mehsh.recvloss_percent.{}", datacenter), basis_points(loss_percent))
+                .map_err(|e| anyhow!(e))?
+                .as_slice(),
+            target,
+        )
+        .await?;
+
+        send_rolling_metrics(sock, target, &datacenter, &event.rolling).await?;
+        send_latency_metrics(
+            sock,
+            target,
+            &datacenter,
+            [
+                event.avg_latency,
+                event.p50_latency,
+                event.p90_latency,
+                event.p99_latency,
+                event.jitter,
+            ],
+        )
+        .await
+    }
+
+    /// the loss of a label is already sent with every server event, see `mehsh.recvloss_label`.
+    pub async fn on_udp_echo_analyzer_event_label(
+        &self,
+        sock: &mut UdpSocket,
+        event: UdpEchoAnalyzerEventLabel,
+    ) -> Result<(), ::anyhow::Error> {
+        let target: SocketAddrV4 = UDPSERVER_ENDPOINT.parse()?;
+        let label = format!("label.{}.{}", event.label, metric_name_part(&event.value));
+
+        let loss_percent = match MaintenanceMode::is_active().await {
+            true => 0.0,
+            false => event.loss_percent,
+        };
+        sock.send_to(
+            create_package_peak(format!("mehsh.recvloss_percent.{}", label), basis_points(loss_percent))
+                .map_err(|e| anyhow!(e))?
+                .as_slice(),
+            target,
+        )
+        .await?;
+
+        send_latency_metrics(
+            sock,
+            target,
+            &label,
+            [
+                event.avg_latency,
+                event.p50_latency,
+                event.p90_latency,
+                event.p99_latency,
+                event.jitter,
+            ],
+        )
+        .await
+    }
+}
+
+/// avg, p50, p90, p99 and jitter in µs, windows without responses are not sent.
+async fn send_latency_metrics(
+    sock: &mut UdpSocket,
+    target: SocketAddrV4,
+    to: &str,
+    latencies: [Option<u128>; 5],
+) -> Result<(), ::anyhow::Error> {
+    let names = ["latency_avg", "latency_p50", "latency_p90", "latency_p99", "jitter"];
+    for (name, latency) in names.iter().zip(latencies) {
+        if let Some(latency) = latency {
+            sock.send_to(
+                create_package_peak(format!("mehsh.{}.{}", name, to), latency.min(i32::MAX as u128) as i32)
+                    .map_err(|e| anyhow!(e))?
+                    .as_slice(),
+                target,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// loss in hundredths of a percent and latency in µs over 1, 5 and 15 minutes.
+/// like the other loss metrics no loss is sent in maintenance mode.
+async fn send_rolling_metrics(
+    sock: &mut UdpSocket,
+    target: SocketAddrV4,
+    to: &str,
+    rolling: &UdpEchoRollingStats,
+) -> Result<(), ::anyhow::Error> {
+    let mut metrics = vec![];
+    if !MaintenanceMode::is_active().await {
+        for (period, loss_percent) in [
+            ("1m", rolling.loss_percent_1m),
+            ("5m", rolling.loss_percent_5m),
+            ("15m", rolling.loss_percent_15m),
+        ] {
+            if let Some(loss_percent) = loss_percent {
+                metrics.push((format!("mehsh.recvloss_percent_{}.{}", period, to), basis_points(loss_percent)));
+            }
+        }
+    }
+    for (period, latency) in [
+        ("1m", rolling.avg_latency_1m),
+        ("5m", rolling.avg_latency_5m),
+        ("15m", rolling.avg_latency_15m),
+    ] {
+        if let Some(latency) = latency {
+            metrics.push((format!("mehsh.latency_avg_{}.{}", period, to), latency.min(i32::MAX as u128) as i32));
+        }
+    }
+
+    for (name, value) in metrics {
+        sock.send_to(
+            create_package_peak(name, value)
+                .map_err(|e| anyhow!(e))?
+                .as_slice(),
+            target,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// metrics are integers, percentages are sent in hundredths of a percent.
+fn basis_points(percent: f64) -> i32 {
+    (percent * 100.0).round() as i32
+}
+
+/// ipv6 addresses contain ':' which is not allowed in a metric name.
+fn metric_name_part(value: &str) -> String {
+    value.replace(':', "_")
 }
@@ -0,0 +1,40 @@
+use anyhow::{anyhow, Context};
+use mehsh_common::config::{Config, ServerIdentifier};
+use std::net::IpAddr;
+
+/// value of `--name` that selects the server by the addresses of the local interfaces.
+pub const AUTO_NAME: &str = "auto";
+
+/// the single server that has one of the addresses of the local interfaces.
+pub fn detect_server_name(config: &Config) -> Result<ServerIdentifier, ::anyhow::Error> {
+    let local_ips = if_addrs::get_if_addrs()
+        .context("--name=auto: could not read the addresses of the local interfaces")?
+        .iter()
+        .map(|interface| interface.ip())
+        .collect::<Vec<_>>();
+
+    let servers = config.servers_with_ips(&local_ips);
+
+    match servers.as_slice() {
+        [server] => {
+            println!("--name=auto: this is server {}", server.identifier);
+            Ok(server.identifier.clone())
+        }
+        [] => Err(anyhow!(
+            "--name=auto: no server has one of the local addresses {}, use --name",
+            format_ips(&local_ips)
+        )),
+        _ => Err(anyhow!(
+            "--name=auto: the local addresses match multiple servers ({}), use --name",
+            servers
+                .iter()
+                .map(|s| s.identifier.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+fn format_ips(ips: &[IpAddr]) -> String {
+    ips.iter().map(|ip| ip.to_string()).collect::<Vec<_>>().join(", ")
+}
@@ -1,7 +1,12 @@
-use crate::udp_echo::analyzer_event::{UdpEchoAnalyzerEventDatacenter, UdpEchoAnalyzerEventServer};
+use crate::udp_echo::analyzer_event::{
+    UdpEchoAnalyzerEventDatacenter, UdpEchoAnalyzerEventLabel, UdpEchoAnalyzerEventServer,
+    UdpEchoServerStats,
+};
 
 #[derive(Clone, Debug)]
 pub enum BroadcastEvent {
     UdpEchoAnalyzerEventServer(UdpEchoAnalyzerEventServer),
     UdpEchoAnalyzerEventDatacenter(UdpEchoAnalyzerEventDatacenter),
+    UdpEchoAnalyzerEventLabel(UdpEchoAnalyzerEventLabel),
+    UdpEchoServerStats(UdpEchoServerStats),
 }
@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::time;
+
+/// notifies about a changed config, either on SIGHUP or when the mtime of one of the
+/// config files (or include directories) changes.
+pub struct ConfigWatcher {
+    paths: Vec<PathBuf>,
+    modified: Vec<Option<SystemTime>>,
+    sighup: Signal,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf) -> Result<Self, ::anyhow::Error> {
+        let mut watcher = Self {
+            paths: vec![],
+            modified: vec![],
+            sighup: signal(SignalKind::hangup())?,
+        };
+
+        watcher.watch(vec![path]);
+
+        Ok(watcher)
+    }
+
+    /// replaces the watched paths, e.g. after includes changed.
+    pub fn watch(&mut self, paths: Vec<PathBuf>) {
+        self.paths = paths;
+        self.modified = self.read_modified();
+    }
+
+    fn read_modified(&self) -> Vec<Option<SystemTime>> {
+        self.paths
+            .iter()
+            .map(|path| ::std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+
+    /// waits until the config should be reloaded, returns the reason.
+    pub async fn changed(&mut self) -> &'static str {
+        let mut interval = time::interval(Duration::from_secs(5));
+
+        loop {
+            tokio::select! {
+                _ = self.sighup.recv() => {
+                    self.modified = self.read_modified();
+                    return "SIGHUP";
+                }
+                _ = interval.tick() => {
+                    let modified = self.read_modified();
+                    if modified != self.modified {
+                        self.modified = modified;
+                        return "file changed";
+                    }
+                }
+            }
+        }
+    }
+}
@@ -2,6 +2,7 @@ use futures::channel::mpsc::{channel, Receiver, Sender};
 use futures::StreamExt;
 use mehsh_common::config::Config;
 use reqwest::StatusCode;
+use tokio::sync::watch;
 use tokio::time::Duration;
 
 pub struct HttpAnalyzerEvent {
@@ -15,13 +16,13 @@ impl HttpAnalyzerEvent {
 }
 
 pub struct HttpAnalyzer {
-    config: Config,
+    config: watch::Receiver<Config>,
     receiver: Receiver<HttpAnalyzerEvent>,
     sender: Sender<HttpAnalyzerEvent>,
 }
 
 impl HttpAnalyzer {
-    pub fn new(config: Config) -> Self {
+    pub fn new(config: watch::Receiver<Config>) -> Self {
         let (sender, receiver) = channel(1000);
 
         Self {
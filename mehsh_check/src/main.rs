@@ -1,46 +1,99 @@
 #![allow(dead_code)]
 
 use crate::analysis::analysis_command::ExecuteAnalysisCommandHandler;
+use crate::auto_name::{detect_server_name, AUTO_NAME};
 use crate::analyzer_event::analyzer_event_subsciber_stdout::AnalyzerEventSubscriverStout;
-use crate::analyzer_event::analyzer_event_subscriber_analysis::AnalyzerEventSubscriberAnalysis;
-use crate::analyzer_event::analyzer_event_subscriber_udp_metric::AnalyzerEventSubscriberUdpMetric;
 use crate::broadcast::BroadcastEvent;
 use crate::http::http_analyzer::HttpAnalyzer;
-use crate::http::http_check::HttpCheck;
-use mehsh_common::config::Config;
-use std::path::PathBuf;
-use clap::Parser;
+use crate::resolver::HostResolver;
+use crate::supervisor::Supervisor;
+use crate::udp_echo::key::{PacketKeys, KEY_ENV};
+use mehsh_common::config::{Config, ConfigObserver, IpVersion};
+use std::path::{Path, PathBuf};
+use anyhow::anyhow;
+use clap::{Parser, Subcommand};
 use tokio::runtime::{Builder, Runtime};
 use udp_echo::analyzer::Analyzer;
-use udp_echo::client::Client;
-use udp_echo::server::Server;
 
 pub mod analysis;
+pub mod auto_name;
 pub mod analyzer_event;
 pub mod broadcast;
+pub mod config_watcher;
 pub mod http;
 pub mod udp_echo;
 pub mod maintenance_mode;
+pub mod resolver;
+pub mod respond;
+pub mod supervisor;
+pub mod validate;
 
 #[macro_use]
 extern crate mehsh_common;
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Opt {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Files to process
-    #[arg(name = "config")]
-    config: PathBuf,
+    #[arg(name = "config", required = true)]
+    config: Option<PathBuf>,
 
+    /// name of this server in the config, "[hostname]" is replaced with the hostname,
+    /// "auto" selects the server by the addresses of the local interfaces.
     #[arg(long = "name", default_value = "[hostname]")]
     name: String,
-    /*
-    #[structopt(long = "privatekey")]
-    privatekey: String,
-    */
+
+    /// Run outside of the mesh, only probe the servers selected by --to
+    #[arg(long = "observer", requires = "to")]
+    observer: bool,
+
+    /// Servers the observer probes, a server, group or selector
+    #[arg(long = "to", requires = "observer")]
+    to: Option<String>,
+
+    /// Address families the observer probes: v4, v6 or both
+    #[arg(long = "ip-version", requires = "observer", default_value = "both")]
+    ip_version: IpVersion,
+
+    /// File with the keys probes are signed with, one `id:key` per line, defaults to the environment variable MEHSH_KEY
+    #[arg(long = "key-file")]
+    key_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Validates the config, exits with 1 if the config has errors
+    Validate {
+        #[arg(name = "config")]
+        config: PathBuf,
+    },
+    /// Only answers probes, does not need a config
+    Respond {
+        /// Address to listen on, e.g. 0.0.0.0:4232 or [::]:4232, can be repeated
+        #[arg(long = "bind")]
+        bind: Vec<String>,
+        /// Only answer these sources, e.g. v4:10.0.0.1, v4:10.0.0.1:4000-5000 or v6:[fd00::1]:4232, can be repeated
+        #[arg(long = "allow")]
+        allow: Vec<String>,
+        /// Maximum probes per second and source address
+        #[arg(long = "rate-limit")]
+        rate_limit: Option<u32>,
+        /// File with the keys probes are signed with, one `id:key` per line, defaults to the environment variable MEHSH_KEY
+        #[arg(long = "key-file")]
+        key_file: Option<PathBuf>,
+    },
 }
 
 fn main() {
     let opt = Opt::parse();
+
+    if let Some(Command::Validate { config }) = opt.command {
+        ::std::process::exit(validate::run_validate(config));
+    }
+
     println!("opt: {:#?}", &opt);
 
     let rt: Runtime = Builder::new_multi_thread()
@@ -49,6 +102,10 @@ fn main() {
         .build()
         .expect("could not build runtime");
 
+    if let Some(Command::Respond { bind, allow, rate_limit, key_file }) = opt.command {
+        ::std::process::exit(respond::run_respond(bind, allow, rate_limit, key_file, rt));
+    }
+
     match try_main(opt, rt) {
         Err(err) => {
             eprintln!("{:?}", &err);
@@ -62,93 +119,69 @@ fn main() {
 }
 
 fn try_main(opt: Opt, rt: Runtime) -> Result<(), ::anyhow::Error> {
-    let name_self = opt.name.replace(
-        "[hostname]",
-        hostname::get()
-            .expect("Hostname should be a string!")
-            .into_string()
-            .expect("Hostname should be a string!")
-            .as_str(),
-    );
+    let config_path = opt.config.expect("config is required by clap");
 
-    let config = Config::new_from_file(name_self.clone(), opt.config)?;
+    let mut resolver = HostResolver::default();
 
-    println!("{:#?}", &config);
+    let keys = load_keys(opt.key_file.as_deref())?;
 
-    let (broardcast_sender, broardcast_recv) =
-        ::tokio::sync::broadcast::channel::<BroadcastEvent>(1000);
+    let observer = match opt.to {
+        Some(to) if opt.observer => Some(ConfigObserver {
+            to,
+            ip_version: opt.ip_version,
+        }),
+        _ => None,
+    };
 
-    if config.get_server_self().serverdensity_udp_agent {
-        let udp_boardcast_recv = broardcast_sender.subscribe();
-        rt.spawn(async move {
-            AnalyzerEventSubscriberUdpMetric::new(udp_boardcast_recv)
-                .run()
-                .await
-        });
+    if observer.is_some() && opt.name == AUTO_NAME {
+        return Err(anyhow!("--name=auto can not be used with --observer, an observer is not a server of the config"));
     }
 
-    for analysis_entry in config.all_analyisis()?.into_iter() {
-        if analysis_entry.from.identifier.to_string() != name_self.as_str() {
-            continue;
-        }
-
-        if analysis_entry.to.identifier.to_string() == name_self.as_str() {
-            continue;
-        }
+    let name_self = if opt.name == AUTO_NAME {
+        // servers with dns names can be detected as well.
+        let (config, _) = rt.block_on(resolver.resolve(&Config::parse_file(config_path.clone())?));
+        detect_server_name(&config)?
+    } else {
+        opt.name.replace(
+            "[hostname]",
+            hostname::get()
+                .expect("Hostname should be a string!")
+                .into_string()
+                .expect("Hostname should be a string!")
+                .as_str(),
+        )
+    };
+
+    let parsed_config = Supervisor::load_config(&name_self, &observer, &config_path)?;
+    let (config, _) = rt.block_on(resolver.resolve(&parsed_config));
 
-        println!(
-            "preparing analysis {} from: {} to: {}",
-            &analysis_entry.name, &analysis_entry.from.identifier, &analysis_entry.to.identifier
-        );
+    println!("{:#?}", &config);
 
-        let udp_boardcast_recv = broardcast_sender.subscribe();
+    let (broardcast_sender, broardcast_recv) =
+        ::tokio::sync::broadcast::channel::<BroadcastEvent>(1000);
 
-        rt.spawn(async move {
-            AnalyzerEventSubscriberAnalysis::new(analysis_entry, udp_boardcast_recv)
-                .run()
-                .await;
-        });
-    }
+    let (config_sender, config_recv) = ::tokio::sync::watch::channel(config.clone());
 
-    let udp_analyzer = Analyzer::new(config.clone());
+    let udp_analyzer = Analyzer::new(config_recv.clone());
     let udp_analyzer_sender = udp_analyzer.get_sender_handle();
-    rt.spawn(async move { udp_analyzer.run(broardcast_sender).await });
+    let udp_analyzer_broadcast_sender = broardcast_sender.clone();
+    rt.spawn(async move { udp_analyzer.run(udp_analyzer_broadcast_sender).await });
 
-    let http_analyzer = HttpAnalyzer::new(config.clone());
+    let http_analyzer = HttpAnalyzer::new(config_recv);
     let http_analyzer_sender = http_analyzer.get_sender_handle();
     rt.spawn(async move { http_analyzer.run().await });
 
-    let handle = rt.spawn(async move { Server::new("0.0.0.0:4232").await?.run().await });
-
-    for check in config.all_checks()?.into_iter() {
-        if check.from.identifier.to_string() != name_self.as_str() {
-            continue;
-        }
+    let mut supervisor = Supervisor::new(
+        name_self,
+        broardcast_sender,
+        udp_analyzer_sender,
+        http_analyzer_sender,
+        resolver,
+        observer,
+        keys,
+    );
 
-        match check.check.as_str() {
-            "udp_ping" => {
-                let client_analyzer_sender = udp_analyzer_sender.clone();
-                println!("starting check to {}", &check.to.identifier);
-                rt.spawn(async move {
-                    Client::new(check.clone(), client_analyzer_sender)
-                        .await?
-                        .run()
-                        .await
-                });
-            }
-            "http" => {
-                let client_analyzer_sender = http_analyzer_sender.clone();
-                rt.spawn(async move {
-                    HttpCheck::new(check.clone(), client_analyzer_sender)
-                        .run()
-                        .await
-                });
-            }
-            _ => {
-                panic!("unknown check.");
-            }
-        }
-    }
+    rt.block_on(supervisor.apply(&config))?;
 
     rt.spawn(async move {
         AnalyzerEventSubscriverStout::new(broardcast_recv)
@@ -156,9 +189,32 @@ fn try_main(opt: Opt, rt: Runtime) -> Result<(), ::anyhow::Error> {
             .await
     });
 
-    rt.block_on(handle)
-        .expect("could not block on handle")
-        .expect("could not block on handle#2");
+    rt.block_on(supervisor.run_reload_loop(config_path, parsed_config, config_sender))?;
 
     Ok(())
 }
+
+/// the keys probes are signed with, see `PacketKeys::load`.
+pub fn load_keys(key_file: Option<&Path>) -> Result<Option<PacketKeys>, ::anyhow::Error> {
+    let keys = match PacketKeys::load(key_file)? {
+        Some(keys) => keys,
+        None => {
+            println!("probes are not signed, use --key-file or {} to sign them", KEY_ENV);
+            return Ok(None);
+        }
+    };
+
+    let source = match key_file {
+        Some(path) => path.display().to_string(),
+        None => KEY_ENV.to_string(),
+    };
+
+    println!(
+        "probes are signed with key {} of {}, accepted older keys: {:?}",
+        keys.primary().get_id(),
+        source,
+        keys.accepted_ids()
+    );
+
+    Ok(Some(keys))
+}
@@ -0,0 +1,75 @@
+use mehsh_common::config::Config;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+/// how often dns names of servers are resolved again.
+pub const RESOLVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// resolves the dns names of the servers and remembers the last known addresses.
+#[derive(Default)]
+pub struct HostResolver {
+    resolved: HashMap<String, Vec<IpAddr>>,
+}
+
+impl HostResolver {
+    /// the config with resolved addresses and whether an address changed since the last call.
+    /// if a name can not be resolved the last known addresses are kept.
+    pub async fn resolve(&mut self, config: &Config) -> (Config, bool) {
+        let hostnames = config.hostnames();
+        self.resolved.retain(|hostname, _| hostnames.contains(hostname));
+
+        let mut changed = false;
+        for hostname in hostnames {
+            let addrs = match Self::lookup(&hostname).await {
+                Ok(addrs) => addrs,
+                Err(e) => {
+                    eprintln!("could not resolve {}, keep the last known addresses: {}", hostname, e);
+                    continue;
+                }
+            };
+
+            match self.resolved.get(&hostname) {
+                Some(previous) if previous == &addrs => continue,
+                Some(previous) => println!(
+                    "addresses of {} changed from {} to {}",
+                    hostname,
+                    Self::format_addrs(previous),
+                    Self::format_addrs(&addrs)
+                ),
+                None => println!("resolved {} to {}", hostname, Self::format_addrs(&addrs)),
+            };
+
+            self.resolved.insert(hostname, addrs);
+            changed = true;
+        }
+
+        (config.with_resolved_hostnames(&self.resolved), changed)
+    }
+
+    pub async fn lookup(hostname: &str) -> Result<Vec<IpAddr>, io::Error> {
+        Self::sorted(::tokio::net::lookup_host((hostname, 0)).await?)
+    }
+
+    pub fn lookup_blocking(hostname: &str) -> Result<Vec<IpAddr>, io::Error> {
+        Self::sorted((hostname, 0).to_socket_addrs()?)
+    }
+
+    /// sorted, so a dns server that rotates the order does not look like a change.
+    fn sorted(addrs: impl Iterator<Item = SocketAddr>) -> Result<Vec<IpAddr>, io::Error> {
+        let mut addrs = addrs.map(|a| a.ip()).collect::<Vec<_>>();
+        addrs.sort();
+        addrs.dedup();
+
+        if addrs.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no addresses"));
+        }
+
+        Ok(addrs)
+    }
+
+    pub fn format_addrs(addrs: &[IpAddr]) -> String {
+        addrs.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ")
+    }
+}
@@ -0,0 +1,87 @@
+use crate::analyzer_event::analyzer_event_subsciber_stdout::AnalyzerEventSubscriverStout;
+use crate::broadcast::BroadcastEvent;
+use crate::udp_echo::server::Server;
+use anyhow::anyhow;
+use futures::future;
+use tokio::sync::watch;
+use mehsh_common::config::allow_addr::AllowAddr;
+use mehsh_common::config::{ConfigResponderFilter, DEFAULT_PORT};
+use std::path::PathBuf;
+use tokio::runtime::Runtime;
+
+/// only answers probes, without config, checks or analysis. returns the exit code.
+/// listens on `[::]` / `0.0.0.0` and the default port if no bind address is given.
+pub fn run_respond(
+    binds: Vec<String>,
+    allow: Vec<String>,
+    rate_limit: Option<u32>,
+    key_file: Option<PathBuf>,
+    rt: Runtime,
+) -> i32 {
+    match rt.block_on(respond(binds, allow, rate_limit, key_file)) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("responder failed: {:#}", e);
+            1
+        }
+    }
+}
+
+fn filter(allow: Vec<String>, rate_limit: Option<u32>) -> Result<ConfigResponderFilter, ::anyhow::Error> {
+    if rate_limit == Some(0) {
+        return Err(anyhow!("--rate-limit must be greater than 0"));
+    }
+
+    let allow = match allow.is_empty() {
+        true => None,
+        false => Some(
+            allow
+                .iter()
+                .map(|a| match AllowAddr::new_from_str(a) {
+                    Ok(AllowAddr::Server(..) | AllowAddr::Group(..)) => {
+                        Err(anyhow!("invalid --allow {}: servers and groups need a config", a))
+                    }
+                    Ok(allow) => Ok(allow),
+                    Err(e) => Err(anyhow!("invalid --allow {}: {}", a, e)),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+    };
+
+    Ok(ConfigResponderFilter { allow, rate_limit })
+}
+
+async fn respond(
+    binds: Vec<String>,
+    allow: Vec<String>,
+    rate_limit: Option<u32>,
+    key_file: Option<PathBuf>,
+) -> Result<(), ::anyhow::Error> {
+    // the filter never changes without a config.
+    let (_, filter) = watch::channel(filter(allow, rate_limit)?);
+    let keys = crate::load_keys(key_file.as_deref())?;
+    let (broadcast_sender, broadcast_recv) = ::tokio::sync::broadcast::channel::<BroadcastEvent>(1000);
+    ::tokio::spawn(AnalyzerEventSubscriverStout::new(broadcast_recv).run());
+
+    let mut servers = vec![];
+
+    if binds.is_empty() {
+        let server = Server::new_dual_stack(DEFAULT_PORT).await?;
+        servers.push(server.with_stats(format!("*:{}", DEFAULT_PORT), broadcast_sender.clone()));
+        println!("responder listening on port {}", DEFAULT_PORT);
+    }
+
+    for bind in binds {
+        let server = Server::new(&bind).await?;
+        servers.push(server.with_stats(bind.clone(), broadcast_sender.clone()));
+        println!("responder listening on {}", bind);
+    }
+
+    let handles = servers
+        .into_iter()
+        .map(|s| Box::pin(s.with_filter(filter.clone()).with_keys(keys.clone()).run()));
+
+    let (res, _, _) = future::select_all(handles).await;
+
+    res
+}
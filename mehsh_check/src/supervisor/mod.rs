@@ -0,0 +1,322 @@
+use crate::analyzer_event::analyzer_event_subscriber_analysis::AnalyzerEventSubscriberAnalysis;
+use crate::analyzer_event::analyzer_event_subscriber_udp_metric::AnalyzerEventSubscriberUdpMetric;
+use crate::broadcast::BroadcastEvent;
+use crate::config_watcher::ConfigWatcher;
+use crate::http::http_analyzer::HttpAnalyzerEvent;
+use crate::http::http_check::HttpCheck;
+use crate::resolver::{HostResolver, RESOLVE_INTERVAL};
+use crate::udp_echo::analyzer::AnalyzerEvent;
+use crate::udp_echo::client::Client;
+use crate::udp_echo::key::PacketKeys;
+use crate::udp_echo::server::Server;
+use futures::channel::mpsc::Sender;
+use mehsh_common::config::{
+    Config, ConfigAnalysis, ConfigCheck, ConfigListener, ConfigObserver, ConfigResponderFilter,
+    ServerIdentifier,
+};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// owns every task that depends on the config.
+/// on a config change only the tasks of added or removed checks / analysis / listeners are started or stopped.
+pub struct Supervisor {
+    name_self: ServerIdentifier,
+    broadcast_sender: ::tokio::sync::broadcast::Sender<BroadcastEvent>,
+    udp_analyzer_sender: Sender<AnalyzerEvent>,
+    http_analyzer_sender: Sender<HttpAnalyzerEvent>,
+    resolver: HostResolver,
+    observer: Option<ConfigObserver>,
+    keys: Option<PacketKeys>,
+    checks: HashMap<ConfigCheck, JoinHandle<()>>,
+    analysis: HashMap<ConfigAnalysis, JoinHandle<()>>,
+    servers: HashMap<ConfigListener, JoinHandle<()>>,
+    /// read by every echo server for each request.
+    filter: watch::Sender<ConfigResponderFilter>,
+    udp_metric: Option<JoinHandle<()>>,
+}
+
+impl Supervisor {
+    pub fn new(
+        name_self: ServerIdentifier,
+        broadcast_sender: ::tokio::sync::broadcast::Sender<BroadcastEvent>,
+        udp_analyzer_sender: Sender<AnalyzerEvent>,
+        http_analyzer_sender: Sender<HttpAnalyzerEvent>,
+        resolver: HostResolver,
+        observer: Option<ConfigObserver>,
+        keys: Option<PacketKeys>,
+    ) -> Self {
+        Self {
+            name_self,
+            broadcast_sender,
+            udp_analyzer_sender,
+            http_analyzer_sender,
+            resolver,
+            observer,
+            keys,
+            checks: HashMap::new(),
+            analysis: HashMap::new(),
+            servers: HashMap::new(),
+            filter: watch::channel(ConfigResponderFilter::default()).0,
+            udp_metric: None,
+        }
+    }
+
+    /// nothing is changed if the config can not be applied.
+    pub async fn apply(&mut self, config: &Config) -> Result<(), ::anyhow::Error> {
+        // everything that can fail comes before the first task is started or stopped.
+        let checks = config.self_checks()?;
+
+        let analysis = config
+            .all_analyisis()?
+            .into_iter()
+            .filter(|a| a.from.identifier == self.name_self)
+            .filter(|a| a.to.identifier != self.name_self)
+            .collect::<Vec<_>>();
+
+        let listeners = config.listeners()?;
+        let filter = config.responder_filter()?;
+
+        self.checks.retain(|check, handle| {
+            if checks.contains(check) {
+                return true;
+            }
+
+            println!("stopping check {} to {} ({})", &check.check, &check.to.identifier, check.to.ip.to_string());
+            handle.abort();
+            false
+        });
+
+        for check in checks {
+            if self.checks.contains_key(&check) {
+                continue;
+            }
+
+            let handle = self.spawn_check(check.clone());
+            self.checks.insert(check, handle);
+        }
+
+        self.analysis.retain(|analysis_entry, handle| {
+            if analysis.contains(analysis_entry) {
+                return true;
+            }
+
+            println!(
+                "stopping analysis {} from: {} to: {}",
+                &analysis_entry.name, &analysis_entry.from.identifier, &analysis_entry.to.identifier
+            );
+            handle.abort();
+            false
+        });
+
+        for analysis_entry in analysis {
+            if self.analysis.contains_key(&analysis_entry) {
+                continue;
+            }
+
+            println!(
+                "preparing analysis {} from: {} to: {}",
+                &analysis_entry.name, &analysis_entry.from.identifier, &analysis_entry.to.identifier
+            );
+
+            let udp_boardcast_recv = self.broadcast_sender.subscribe();
+            let analysis_config = analysis_entry.clone();
+            let handle = ::tokio::spawn(async move {
+                AnalyzerEventSubscriberAnalysis::new(analysis_config, udp_boardcast_recv)
+                    .run()
+                    .await;
+            });
+            self.analysis.insert(analysis_entry, handle);
+        }
+
+        self.filter.send_if_modified(|current| {
+            if *current == filter {
+                return false;
+            }
+
+            println!("responder filter changed");
+            *current = filter;
+            true
+        });
+
+        // listeners that could not bind are retried.
+        self.servers.retain(|_, handle| !handle.is_finished());
+
+        let stopped = self
+            .servers
+            .keys()
+            .filter(|l| !listeners.contains(l))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for listener in stopped {
+            if let Some(handle) = self.servers.remove(&listener) {
+                println!("stopping echo server on {}", listener);
+                handle.abort();
+                // the socket is closed once the task is gone, a new listener on the same address could not bind before.
+                let _ = handle.await;
+            }
+        }
+
+        for listener in listeners {
+            if self.servers.contains_key(&listener) {
+                continue;
+            }
+
+            let server_filter = self.filter.subscribe();
+            let broadcast_sender = self.broadcast_sender.clone();
+            let keys = self.keys.clone();
+            let handle = ::tokio::spawn(async move {
+                let server = match listener.ip {
+                    None => Server::new_dual_stack(listener.port).await,
+                    Some(ip) => Server::new(&SocketAddr::new(ip, listener.port).to_string()).await,
+                };
+
+                // a listener that can not bind does not stop the other listeners or the checks.
+                let server = match server {
+                    Ok(s) => s,
+                    Err(e) => {
+                        eprintln!("echo server could not listen on {}: {:#}", listener, e);
+                        return;
+                    }
+                };
+
+                println!("echo server listening on {}", listener);
+                let server = server
+                    .with_filter(server_filter)
+                    .with_keys(keys)
+                    .with_stats(listener.to_string(), broadcast_sender);
+
+                if let Err(e) = server.run().await {
+                    eprintln!("echo server on {} failed: {:#}", listener, e);
+                }
+            });
+            self.servers.insert(listener, handle);
+        }
+
+        let udp_metric_enabled = config
+            .get_server_self()
+            .map(|s| s.serverdensity_udp_agent)
+            .unwrap_or(false);
+
+        match (udp_metric_enabled, self.udp_metric.take()) {
+            (true, Some(handle)) => self.udp_metric = Some(handle),
+            (true, None) => {
+                let udp_boardcast_recv = self.broadcast_sender.subscribe();
+                self.udp_metric = Some(::tokio::spawn(async move {
+                    AnalyzerEventSubscriberUdpMetric::new(udp_boardcast_recv)
+                        .run()
+                        .await
+                }));
+            }
+            (false, Some(handle)) => handle.abort(),
+            (false, None) => {}
+        };
+
+        Ok(())
+    }
+
+    fn spawn_check(&self, check: ConfigCheck) -> JoinHandle<()> {
+        match check.check.as_str() {
+            "udp_ping" => {
+                let client_analyzer_sender = self.udp_analyzer_sender.clone();
+                let keys = self.keys.clone();
+                println!("starting check to {} ({})", &check.to.identifier, check.to.ip.to_string());
+                ::tokio::spawn(async move {
+                    let res = match Client::new(check.clone(), client_analyzer_sender, keys).await {
+                        Ok(client) => client.run().await,
+                        Err(e) => Err(e),
+                    };
+
+                    if let Err(e) = res {
+                        eprintln!("check to {} failed: {:?}", &check.to.identifier, e);
+                    }
+                })
+            }
+            "http" => {
+                let client_analyzer_sender = self.http_analyzer_sender.clone();
+                ::tokio::spawn(async move {
+                    HttpCheck::new(check.clone(), client_analyzer_sender)
+                        .run()
+                        .await
+                })
+            }
+            _ => {
+                // unknown checks are rejected when the config is loaded.
+                ::tokio::spawn(async move {
+                    eprintln!("unknown check {}", &check.check);
+                })
+            }
+        }
+    }
+
+    /// loads the config for the server or for the observer.
+    pub fn load_config(
+        name_self: &ServerIdentifier,
+        observer: &Option<ConfigObserver>,
+        config_path: &Path,
+    ) -> Result<Config, ::anyhow::Error> {
+        match observer {
+            None => Config::new_from_file(name_self.clone(), config_path.to_path_buf()),
+            Some(o) => Config::new_observer_from_file(name_self.clone(), o.clone(), config_path.to_path_buf()),
+        }
+    }
+
+    fn watched_paths(config_path: &Path, config: &Config) -> Vec<PathBuf> {
+        let mut paths = vec![config_path.to_path_buf()];
+        paths.extend(config.watched_paths().iter().cloned());
+        paths
+    }
+
+    /// reloads the config on every change, an invalid config keeps the old one running.
+    /// dns names are resolved again periodically, checks to changed addresses are restarted.
+    /// `parsed_config` is the config before dns names were resolved.
+    pub async fn run_reload_loop(
+        mut self,
+        config_path: PathBuf,
+        mut parsed_config: Config,
+        config_sender: watch::Sender<Config>,
+    ) -> Result<(), ::anyhow::Error> {
+        let mut watcher = ConfigWatcher::new(config_path.clone())?;
+        watcher.watch(Self::watched_paths(&config_path, &config_sender.borrow()));
+
+        let mut resolve_interval = time::interval(RESOLVE_INTERVAL);
+        resolve_interval.tick().await;
+
+        loop {
+            let config = ::tokio::select! {
+                reason = watcher.changed() => {
+                    println!("reloading config ({})", reason);
+
+                    parsed_config = match Self::load_config(&self.name_self, &self.observer, &config_path) {
+                        Ok(c) => c,
+                        Err(e) => {
+                            eprintln!("could not reload config, keep running with the old config: {:#}", e);
+                            continue;
+                        }
+                    };
+
+                    self.resolver.resolve(&parsed_config).await.0
+                }
+                _ = resolve_interval.tick() => {
+                    match self.resolver.resolve(&parsed_config).await {
+                        (config, true) => config,
+                        (_, false) => continue,
+                    }
+                }
+            };
+
+            if let Err(e) = self.apply(&config).await {
+                eprintln!("could not apply config, keep running with the old config: {:#}", e);
+                continue;
+            }
+
+            watcher.watch(Self::watched_paths(&config_path, &config));
+            config_sender.send_replace(config);
+            println!("config applied, {} checks, {} analysis", self.checks.len(), self.analysis.len());
+        }
+    }
+}
@@ -1,41 +1,72 @@
-use crate::udp_echo::analyzer_event::{UdpEchoAnalyzerEventDatacenter, UdpEchoAnalyzerEventServer};
-use crate::udp_echo::packet::{Packet, PacketType};
+use crate::udp_echo::analyzer_event::{
+    UdpEchoAnalyzerEventDatacenter, UdpEchoAnalyzerEventLabel, UdpEchoAnalyzerEventServer,
+};
+use crate::udp_echo::histogram::{Jitter, LatencyHistogram};
+use crate::udp_echo::packet::{Packet, PacketType, VERSION_1};
+use crate::udp_echo::rolling::RollingStats;
 use crate::BroadcastEvent;
 use chrono::Local;
 use futures::channel::mpsc::{channel, Receiver, Sender};
 use futures::stream::StreamExt;
-use mehsh_common::config::{Config, ServerIdentifier};
+use mehsh_common::config::allow_addr::AllowIp;
+use mehsh_common::config::{Config, ConfigProbe, Ident, ServerIdentifier};
 use std::cmp::min;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
 use tokio::time;
 
 type RemoteHost = String;
 
+/// a server can be probed on several ports and with different probe settings, every check is its own path with its own packet ids.
+type PathKey = (Ident, ConfigProbe);
+
+/// path, client and packet id, a restarted client counts from 1 again.
+type PacketKey = (PathKey, u64, u64);
+
+/// how long finished requests are remembered to detect late and duplicated responses.
+const FINISHED_RETENTION: Duration = Duration::from_secs(60);
+
 #[derive(Debug)]
 pub struct AnalyzerEvent {
-    server_identifier: String,
+    server: Ident,
+    /// unique per started client, see `Client::new`.
+    client: u64,
+    probe: ConfigProbe,
     packet: Packet,
+    /// when the request was sent or the response was received, not when the event reaches the analyzer.
+    time: SystemTime,
 }
 
 impl AnalyzerEvent {
-    pub fn new(server_identifier: ServerIdentifier, packet: Packet) -> Self {
+    fn path(&self) -> PathKey {
+        (self.server.clone(), self.probe)
+    }
+
+    fn packet_key(&self) -> PacketKey {
+        (self.path(), self.client, self.packet.get_id())
+    }
+
+    pub fn new(server: Ident, client: u64, probe: ConfigProbe, packet: Packet, time: SystemTime) -> Self {
         AnalyzerEvent {
-            server_identifier,
+            server,
+            client,
+            probe,
             packet,
+            time,
         }
     }
 }
 
 pub struct Analyzer {
-    config: Config,
+    config: watch::Receiver<Config>,
     receiver: Receiver<AnalyzerEvent>,
     sender: Sender<AnalyzerEvent>,
 }
 
 impl Analyzer {
-    pub fn new(config: Config) -> Self {
+    pub fn new(config: watch::Receiver<Config>) -> Self {
         let (sender, receiver) = channel(1000);
 
         Analyzer {
@@ -50,16 +81,22 @@ impl Analyzer {
     }
 
     pub async fn run(self, mut broadcast: ::tokio::sync::broadcast::Sender<BroadcastEvent>) {
-        let mut interval = time::interval(Duration::from_millis(5_000));
+        // every check has its own window, the tick only decides how exact a window ends.
+        let mut interval = time::interval(Duration::from_millis(100));
         let mut recv = self.receiver;
+        let mut config = self.config;
 
-        let mut analyzer_stats = AnalyzerStats::new(self.config.clone());
+        let mut analyzer_stats = AnalyzerStats::new(config.borrow().clone());
 
         loop {
             tokio::select! {
+                Ok(_) = config.changed() => {
+                    analyzer_stats.set_config(config.borrow_and_update().clone());
+                }
                 _ = interval.tick() => {
-                    let data = analyzer_stats.slice();
-                    analyzer_stats.aggregate(data, &mut broadcast);
+                    for data in analyzer_stats.slice() {
+                        analyzer_stats.aggregate(data, &mut broadcast);
+                    }
                 }
                 p = recv.next() => {
                     if let Some(msg) = p {
@@ -73,17 +110,33 @@ impl Analyzer {
 
 #[derive(Debug)]
 struct AnalyzerStatsEntry {
-    server_identifier: ServerIdentifier,
+    server: Ident,
+    client: u64,
+    id: u64,
+    probe: ConfigProbe,
     req_time: SystemTime,
     resp_time: Option<SystemTime>,
+    /// time the responder needed to answer, only known for v2 responses.
+    responder_duration: Duration,
+    /// requests that did not reach the responder since the previous response, only known for v2 responses.
+    forward_lost: Option<u64>,
+    /// the response arrived after the response of a later request.
+    reordered: bool,
+    /// responses after the first one.
+    duplicates: u64,
 }
 
 impl AnalyzerStatsEntry {
+    fn path(&self) -> PathKey {
+        (self.server.clone(), self.probe)
+    }
+
     pub fn calculate_latency(&self) -> Option<u128> {
         match self.resp_time {
             Some(resp) => Some(
                 resp.duration_since(self.req_time)
                     .unwrap_or(Duration::from_micros(0))
+                    .saturating_sub(self.responder_duration)
                     .as_micros(),
             ),
             _ => None,
@@ -91,9 +144,63 @@ impl AnalyzerStatsEntry {
     }
 }
 
+/// finished entries of all checks with the same window length.
+struct AnalyzerWindow {
+    /// number of the window since the unix epoch, windows of the same length end at the same time.
+    epoch: u128,
+    entries: Vec<AnalyzerStatsEntry>,
+}
+
+/// the last response of a path with the number of requests the responder received until then.
+#[derive(Default)]
+struct AnalyzerPath {
+    /// the client the ids belong to.
+    client: u64,
+    id: u64,
+    received_count: u64,
+    /// highest id of all responses, also of v1 responses.
+    highest_id: u64,
+    /// over all windows, like the jitter of RFC 3550.
+    jitter: Jitter,
+}
+
+impl AnalyzerPath {
+    /// a restarted client counts from 1 again, everything known about the path belongs to the old client.
+    fn get<'a>(paths: &'a mut HashMap<PathKey, AnalyzerPath>, event: &AnalyzerEvent) -> &'a mut AnalyzerPath {
+        let path = paths.entry(event.path()).or_default();
+        if path.client != event.client {
+            *path = AnalyzerPath {
+                client: event.client,
+                ..Default::default()
+            };
+        }
+        path
+    }
+}
+
+/// a request that timed out, responses to it are late or duplicated.
+struct AnalyzerFinished {
+    req_time: SystemTime,
+    answered: bool,
+}
+
+/// responses to finished requests, reported with the next window of the path.
+#[derive(Default)]
+struct AnalyzerLateResponses {
+    late: u64,
+    duplicates: u64,
+}
+
 struct AnalyzerStats {
     config: Config,
-    map: HashMap<(String, u64), AnalyzerStatsEntry>,
+    map: HashMap<PacketKey, AnalyzerStatsEntry>,
+    windows: HashMap<Duration, AnalyzerWindow>,
+    paths: HashMap<PathKey, AnalyzerPath>,
+    finished: HashMap<PacketKey, AnalyzerFinished>,
+    late: HashMap<PathKey, AnalyzerLateResponses>,
+    /// the window is part of the path, datacenters are averaged separately per window.
+    rolling_servers: HashMap<PathKey, RollingStats>,
+    rolling_datacenters: HashMap<(String, Duration), RollingStats>,
 }
 
 impl AnalyzerStats {
@@ -101,25 +208,54 @@ impl AnalyzerStats {
         AnalyzerStats {
             config,
             map: HashMap::new(),
+            windows: HashMap::new(),
+            paths: HashMap::new(),
+            finished: HashMap::new(),
+            late: HashMap::new(),
+            rolling_servers: HashMap::new(),
+            rolling_datacenters: HashMap::new(),
         }
     }
 
-    pub fn add_event(&mut self, event: AnalyzerEvent) {
-        let now = SystemTime::now();
+    /// everything known about removed servers is dropped, it can not be aggregated with the new config.
+    /// paths of unchanged checks keep counting, restarted clients are detected by `AnalyzerPath::get`.
+    fn set_config(&mut self, config: Config) {
+        let known = |server: &Ident| config.get_server_by_identifier(&server.identifier).is_some();
+
+        self.map.retain(|_, e| known(&e.server));
+        for window in self.windows.values_mut() {
+            window.entries.retain(|e| known(&e.server));
+        }
+        self.windows.retain(|_, w| !w.entries.is_empty());
+        self.finished.retain(|((server, _), _, _), _| known(server));
+        self.paths.retain(|(server, _), _| known(server));
+        self.late.retain(|(server, _), _| known(server));
+        self.rolling_servers.retain(|(server, _), _| known(server));
 
+        self.config = config;
+    }
+
+    pub fn add_event(&mut self, event: AnalyzerEvent) {
         match self
             .map
-            .entry((event.server_identifier.clone(), event.packet.get_id()))
+            .entry(event.packet_key())
         {
             Entry::Vacant(e) => {
                 let stats_entry = match event.packet.get_type() {
                     &PacketType::Req => AnalyzerStatsEntry {
-                        server_identifier: event.server_identifier,
-                        req_time: now.clone(),
+                        id: event.packet.get_id(),
+                        client: event.client,
+                        server: event.server,
+                        probe: event.probe,
+                        req_time: event.time,
                         resp_time: None,
+                        responder_duration: Duration::ZERO,
+                        forward_lost: None,
+                        reordered: false,
+                        duplicates: 0,
                     },
                     &PacketType::Resp => {
-                        // got a response without a request. ignore ...
+                        self.add_finished_response(&event);
                         return;
                     }
                 };
@@ -132,16 +268,93 @@ impl AnalyzerStats {
                         // got request twice? doesnt make sense.
                         return;
                     }
+                    &PacketType::Resp if e.get().resp_time.is_some() => {
+                        e.get_mut().duplicates += 1;
+                    }
                     &PacketType::Resp => {
-                        e.get_mut().resp_time = Some(now);
+                        let forward_lost = Self::forward_lost(&mut self.paths, &event);
+
+                        let path = AnalyzerPath::get(&mut self.paths, &event);
+                        let reordered = event.packet.get_id() < path.highest_id;
+                        path.highest_id = path.highest_id.max(event.packet.get_id());
+
+                        let entry = e.get_mut();
+                        entry.forward_lost = forward_lost;
+                        entry.reordered = reordered;
+                        entry.resp_time = Some(event.time);
+                        entry.responder_duration = event
+                            .packet
+                            .get_timestamps()
+                            .map(|t| t.responder_duration())
+                            .unwrap_or_default();
                     }
                 }
             }
         }
     }
 
-    pub fn slice(&mut self) -> Vec<AnalyzerStatsEntry> {
-        let mut data = vec![];
+    /// a response to a request that already timed out, or a copy of a response.
+    /// responses to unknown requests, e.g. of a restarted client, are ignored.
+    fn add_finished_response(&mut self, event: &AnalyzerEvent) {
+        let finished = match self.finished.get_mut(&event.packet_key()) {
+            Some(f) => f,
+            None => return,
+        };
+
+        let late = self.late.entry(event.path()).or_default();
+        if finished.answered {
+            late.duplicates += 1;
+        } else {
+            finished.answered = true;
+            late.late += 1;
+        }
+    }
+
+    /// requests between the previous and this response that the responder did not receive.
+    /// the responder counts the requests it received from the client, the ids of the client count the sent requests.
+    fn forward_lost(paths: &mut HashMap<PathKey, AnalyzerPath>, event: &AnalyzerEvent) -> Option<u64> {
+        // v1 responses have no count.
+        if event.packet.get_version() == VERSION_1 {
+            return None;
+        }
+
+        let id = event.packet.get_id();
+        let path = AnalyzerPath::get(paths, event);
+
+        // reordered response, the requests before it are already counted.
+        if id <= path.id {
+            return event.packet.get_received_count().map(|_| 0);
+        }
+
+        let received_count = match event.packet.get_received_count() {
+            Some(c) => c,
+            // the responder did not count the request, e.g. its client table was full, the next count starts again.
+            None => {
+                path.id = id;
+                path.received_count = 0;
+                return None;
+            }
+        };
+
+        // a newer request but a lower count, the responder restarted and counts from 1 again.
+        // the same if it did not count before, the loss until then is unknown.
+        if received_count < path.received_count || (path.received_count == 0 && path.id > 0) {
+            path.id = id;
+            path.received_count = received_count;
+            return None;
+        }
+
+        let sent = id - path.id;
+        let received = received_count - path.received_count;
+
+        path.id = id;
+        path.received_count = received_count;
+
+        Some(sent.saturating_sub(received))
+    }
+
+    /// entries of every window that ended, grouped by window length.
+    pub fn slice(&mut self) -> Vec<Vec<AnalyzerStatsEntry>> {
         let now = SystemTime::now();
 
         let mut old_map = HashMap::new();
@@ -156,33 +369,91 @@ impl AnalyzerStats {
                 Ok(d) => d,
             };
 
-            if dur.as_secs() < 1 {
+            if dur < m.probe.timeout {
                 self.map.insert(k, m);
                 continue;
             }
 
-            data.push(m);
+            self.finished.insert(
+                k,
+                AnalyzerFinished {
+                    req_time: m.req_time,
+                    answered: m.resp_time.is_some(),
+                },
+            );
+
+            let epoch = Self::window_epoch(now, m.probe.window);
+            self.windows
+                .entry(m.probe.window)
+                .or_insert_with(|| AnalyzerWindow {
+                    epoch,
+                    entries: vec![],
+                })
+                .entries
+                .push(m);
+        }
+
+        let mut data = vec![];
+        for (window, analyzer_window) in self.windows.iter_mut() {
+            let epoch = Self::window_epoch(now, *window);
+            if epoch == analyzer_window.epoch {
+                continue;
+            }
+
+            analyzer_window.epoch = epoch;
+            data.push(::std::mem::take(&mut analyzer_window.entries));
         }
 
+        self.windows.retain(|_, w| !w.entries.is_empty());
+
+        self.finished.retain(|_, f| {
+            now.duration_since(f.req_time).map(|d| d < FINISHED_RETENTION).unwrap_or(true)
+        });
+
         data
     }
 
+    fn window_epoch(now: SystemTime, window: Duration) -> u128 {
+        let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        since_epoch / window.as_millis().max(1)
+    }
+
     pub fn aggregate(
-        &self,
-        stats_entries: Vec<AnalyzerStatsEntry>,
+        &mut self,
+        mut stats_entries: Vec<AnalyzerStatsEntry>,
         broadcast: &mut ::tokio::sync::broadcast::Sender<BroadcastEvent>,
     ) {
+        // the jitter compares the latency of consecutive probes.
+        stats_entries.sort_by_key(|e| e.id);
+        // all entries of a slice have the same window length.
+        let window = stats_entries.first().map(|e| e.probe.window).unwrap_or_default();
+
         let mut map = HashMap::new();
         for entry in stats_entries.into_iter() {
-            match map.entry(entry.server_identifier.clone()) {
+            let latency = entry.calculate_latency();
+            if let Some(latency) = latency {
+                self.paths.entry(entry.path()).or_default().jitter.add(latency);
+            }
+
+            // every interface, address, port and probe setting of a server is its own path.
+            match map.entry(entry.path()) {
                 Entry::Vacant(e) => {
-                    let latency = entry.calculate_latency();
                     e.insert(AggregatedServerStatsEntry {
-                        remote_server_identifier: entry.server_identifier,
+                        remote_server_identifier: entry.server.identifier.clone(),
+                        remote_server_interface: entry.server.interface.clone(),
+                        remote_server_ip: entry.server.ip.clone(),
+                        remote_server_port: entry.probe.port,
                         req_count: 1,
                         resp_count: if entry.resp_time.is_some() { 1 } else { 0 },
                         min_latency: latency,
                         max_latency: latency,
+                        forward_loss: entry.forward_lost,
+                        in_order: if entry.resp_time.is_some() && !entry.reordered { 1 } else { 0 },
+                        reordered: if entry.reordered { 1 } else { 0 },
+                        duplicates: entry.duplicates,
+                        late: 0,
+                        histogram: LatencyHistogram::default(),
+                        jitter: None,
                     });
                 }
                 Entry::Occupied(mut e) => {
@@ -194,7 +465,18 @@ impl AnalyzerStats {
 
                     mut_entry.req_count += 1;
 
-                    let latency = entry.calculate_latency();
+                    if entry.resp_time.is_some() && !entry.reordered {
+                        mut_entry.in_order += 1;
+                    }
+                    if entry.reordered {
+                        mut_entry.reordered += 1;
+                    }
+                    mut_entry.duplicates += entry.duplicates;
+
+                    mut_entry.forward_loss = match (mut_entry.forward_loss, entry.forward_lost) {
+                        (Some(a), Some(b)) => Some(a + b),
+                        (a, b) => a.or(b),
+                    };
 
                     match (mut_entry.min_latency, latency) {
                         (None, None) => {}
@@ -211,27 +493,68 @@ impl AnalyzerStats {
                     };
                 }
             }
+
+            if let (Some(latency), Some(item)) = (latency, map.get_mut(&entry.path())) {
+                item.histogram.add(latency);
+            }
+        }
+
+        for (server, item) in map.iter_mut() {
+            if let Some(late) = self.late.remove(server) {
+                item.late += late.late;
+                item.duplicates += late.duplicates;
+            }
+
+            item.jitter = self.paths.get(server).and_then(|p| p.jitter.get());
         }
 
         // losses by server
-        let server_self = self.config.get_server_self();
-        for (_, item) in map.iter() {
-            let server_info = self
-                .config
-                .get_server_by_identifier(&item.remote_server_identifier)
-                .expect("could not find server, should never happen");
-            let server_ip = server_info.ip.clone();
+        let server_self_identifier = self.config.get_self_identifier();
+        for (server, item) in map.iter() {
+            // losses of a window can be detected with the first response of the next window.
+            let loss = item.req_count.saturating_sub(item.resp_count);
+            let loss_forward = item.forward_loss.map(|f| min(f, loss));
+
+            let rolling = self.rolling_servers.entry(server.clone()).or_default();
+            rolling.add(
+                window,
+                item.req_count,
+                item.resp_count,
+                loss_percent(loss, item.req_count),
+                item.histogram.mean(),
+            );
 
             match broadcast.send(BroadcastEvent::UdpEchoAnalyzerEventServer(
                 UdpEchoAnalyzerEventServer {
                     date_time: Local::now(),
-                    server_from: server_self.identifier.to_string(),
+                    server_from: server_self_identifier.to_string(),
                     server_to: item.remote_server_identifier.to_string(),
-                    server_to_ip: server_ip,
+                    server_to_ip: item.remote_server_ip.to_string(),
+                    server_to_port: item.remote_server_port,
+                    server_to_interface: item.remote_server_interface.to_string(),
+                    server_to_labels: self
+                        .config
+                        .get_server_by_identifier(&item.remote_server_identifier)
+                        .map(|s| s.labels.clone())
+                        .unwrap_or_default(),
                     req_count: item.req_count,
                     resp_count: item.resp_count,
+                    loss,
+                    loss_percent: loss_percent(loss, item.req_count),
                     max_latency: item.max_latency,
                     min_latency: item.min_latency,
+                    loss_forward,
+                    loss_return: loss_forward.map(|f| loss - f),
+                    in_order: item.in_order,
+                    reordered: item.reordered,
+                    duplicates: item.duplicates,
+                    late: item.late,
+                    avg_latency: item.histogram.mean(),
+                    p50_latency: item.histogram.percentile(50),
+                    p90_latency: item.histogram.percentile(90),
+                    p99_latency: item.histogram.percentile(99),
+                    jitter: item.jitter,
+                    rolling: rolling.get(),
                 },
             )) {
                 Ok(_) => {}
@@ -246,10 +569,10 @@ impl AnalyzerStats {
 
             // losses by dc
             for (_, item) in map.iter() {
-                let server_info = self
-                    .config
-                    .get_server_by_identifier(&item.remote_server_identifier)
-                    .expect("could not find server, should never happen");
+                let server_info = match self.config.get_server_by_identifier(&item.remote_server_identifier) {
+                    Some(s) => s,
+                    None => continue,
+                };
 
                 for datacenter in &server_info.datacenter_as_entries {
                     match buffer.entry(datacenter.to_string()) {
@@ -260,6 +583,13 @@ impl AnalyzerStats {
                                 resp_count: item.resp_count,
                                 min_latency: item.min_latency,
                                 max_latency: item.max_latency,
+                                in_order: item.in_order,
+                                reordered: item.reordered,
+                                duplicates: item.duplicates,
+                                late: item.late,
+                                histogram: item.histogram.clone(),
+                                jitter_sum: item.jitter.unwrap_or(0),
+                                jitter_count: if item.jitter.is_some() { 1 } else { 0 },
                             });
                         }
                         Entry::Occupied(mut e) => {
@@ -267,6 +597,15 @@ impl AnalyzerStats {
 
                             mut_entry.req_count += item.req_count;
                             mut_entry.resp_count += item.resp_count;
+                            mut_entry.in_order += item.in_order;
+                            mut_entry.reordered += item.reordered;
+                            mut_entry.duplicates += item.duplicates;
+                            mut_entry.late += item.late;
+                            mut_entry.histogram.merge(&item.histogram);
+                            if let Some(jitter) = item.jitter {
+                                mut_entry.jitter_sum += jitter;
+                                mut_entry.jitter_count += 1;
+                            }
                             mut_entry.min_latency = min(item.min_latency, mut_entry.min_latency);
                             mut_entry.max_latency = min(item.max_latency, mut_entry.max_latency);
                         }
@@ -280,20 +619,46 @@ impl AnalyzerStats {
         let datacenter_self = self
             .config
             .get_server_self()
-            .datacenter
-            .clone()
+            .and_then(|s| s.datacenter.clone())
             .unwrap_or("".to_string());
         for (_, item) in datacenter_map.iter() {
+            let loss = item.req_count.saturating_sub(item.resp_count);
+
+            let rolling = self.rolling_datacenters.entry((item.datacenter.clone(), window)).or_default();
+            rolling.add(
+                window,
+                item.req_count,
+                item.resp_count,
+                loss_percent(loss, item.req_count),
+                item.histogram.mean(),
+            );
+
             match broadcast.send(BroadcastEvent::UdpEchoAnalyzerEventDatacenter(
                 UdpEchoAnalyzerEventDatacenter {
                     date_time: Local::now(),
-                    server_from: server_self.identifier.to_string(),
+                    server_from: server_self_identifier.to_string(),
                     datacenter_from: datacenter_self.to_string(),
                     datacenter_to: item.datacenter.to_string(),
                     req_count: item.req_count,
                     resp_count: item.resp_count,
+                    loss,
+                    loss_percent: loss_percent(loss, item.req_count),
                     max_latency: item.max_latency,
                     min_latency: item.min_latency,
+                    in_order: item.in_order,
+                    reordered: item.reordered,
+                    duplicates: item.duplicates,
+                    late: item.late,
+                    avg_latency: item.histogram.mean(),
+                    p50_latency: item.histogram.percentile(50),
+                    p90_latency: item.histogram.percentile(90),
+                    p99_latency: item.histogram.percentile(99),
+                    // mean of the jitter of all paths to the datacenter.
+                    jitter: match item.jitter_count {
+                        0 => None,
+                        count => Some(item.jitter_sum / count as u128),
+                    },
+                    rolling: rolling.get(),
                 },
             )) {
                 Ok(_) => {}
@@ -302,23 +667,396 @@ impl AnalyzerStats {
                 }
             };
         }
+
+        self.rolling_servers.retain(|_, r| !r.is_stale());
+        self.rolling_datacenters.retain(|_, r| !r.is_stale());
+
+        // losses by label
+        let mut label_map: HashMap<(String, String), AggregatedLabelStatsEntry> = HashMap::new();
+        for (_, item) in map.iter() {
+            let server_info = match self.config.get_server_by_identifier(&item.remote_server_identifier) {
+                Some(s) => s,
+                None => continue,
+            };
+
+            for label in self.config.get_aggregate_labels() {
+                let value = match server_info.labels.get(label) {
+                    Some(v) => v,
+                    None => continue,
+                };
+
+                let entry = label_map
+                    .entry((label.clone(), value.clone()))
+                    .or_insert_with(|| AggregatedLabelStatsEntry {
+                        req_count: 0,
+                        resp_count: 0,
+                        min_latency: None,
+                        max_latency: None,
+                        histogram: LatencyHistogram::default(),
+                        jitter_sum: 0,
+                        jitter_count: 0,
+                    });
+
+                entry.req_count += item.req_count;
+                entry.resp_count += item.resp_count;
+                entry.histogram.merge(&item.histogram);
+                if let Some(jitter) = item.jitter {
+                    entry.jitter_sum += jitter;
+                    entry.jitter_count += 1;
+                }
+                entry.min_latency = match (entry.min_latency, item.min_latency) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                entry.max_latency = entry.max_latency.max(item.max_latency);
+            }
+        }
+
+        for ((label, value), item) in label_map.iter() {
+            match broadcast.send(BroadcastEvent::UdpEchoAnalyzerEventLabel(
+                UdpEchoAnalyzerEventLabel {
+                    date_time: Local::now(),
+                    server_from: server_self_identifier.to_string(),
+                    label: label.to_string(),
+                    value: value.to_string(),
+                    req_count: item.req_count,
+                    resp_count: item.resp_count,
+                    loss: item.req_count.saturating_sub(item.resp_count),
+                    loss_percent: loss_percent(item.req_count.saturating_sub(item.resp_count), item.req_count),
+                    max_latency: item.max_latency,
+                    min_latency: item.min_latency,
+                    avg_latency: item.histogram.mean(),
+                    p50_latency: item.histogram.percentile(50),
+                    p90_latency: item.histogram.percentile(90),
+                    p99_latency: item.histogram.percentile(99),
+                    // mean of the jitter of all paths with the label.
+                    jitter: match item.jitter_count {
+                        0 => None,
+                        count => Some(item.jitter_sum / count as u128),
+                    },
+                },
+            )) {
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("warning, issue with broadcasting label event: {:?}", e)
+                }
+            };
+        }
+    }
+}
+
+/// 0 if nothing was sent.
+fn loss_percent(loss: u64, req_count: u64) -> f64 {
+    match req_count {
+        0 => 0.0,
+        req_count => loss as f64 * 100.0 / req_count as f64,
     }
 }
 
 struct AggregatedServerStatsEntry {
     remote_server_identifier: ServerIdentifier,
-    req_count: u16,
-    resp_count: u16,
+    remote_server_interface: String,
+    remote_server_ip: AllowIp,
+    remote_server_port: u16,
+    req_count: u64,
+    resp_count: u64,
     min_latency: Option<u128>,
     max_latency: Option<u128>,
-    // avg_latency: u64
+    forward_loss: Option<u64>,
+    in_order: u64,
+    reordered: u64,
+    duplicates: u64,
+    late: u64,
+    /// for the mean and the percentiles.
+    histogram: LatencyHistogram,
+    jitter: Option<u128>,
+}
+
+struct AggregatedLabelStatsEntry {
+    req_count: u64,
+    resp_count: u64,
+    min_latency: Option<u128>,
+    max_latency: Option<u128>,
+    /// for the mean and the percentiles.
+    histogram: LatencyHistogram,
+    jitter_sum: u128,
+    jitter_count: u32,
 }
 
 struct AggregatedDatacenterStatsEntry {
     datacenter: String,
-    req_count: u16,
-    resp_count: u16,
+    req_count: u64,
+    resp_count: u64,
     min_latency: Option<u128>,
     max_latency: Option<u128>,
-    // avg_latency: u64
+    in_order: u64,
+    reordered: u64,
+    duplicates: u64,
+    late: u64,
+    /// for the mean and the percentiles.
+    histogram: LatencyHistogram,
+    jitter_sum: u128,
+    jitter_count: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::udp_echo::packet::VERSION_2;
+    use mehsh_common::config::ConfigCheck;
+    use tokio::sync::broadcast;
+
+    const CONFIG: &str = r#"
+[[server]]
+name = "a"
+ip = "127.0.0.1"
+datacenter = "fra"
+groups = []
+
+[[server]]
+name = "b"
+ip = "127.0.0.2"
+datacenter = "fra"
+groups = []
+
+[[check]]
+from = "a"
+to = "b"
+check = "udp_ping"
+"#;
+
+    const CONFIG_WITHOUT_B: &str = r#"
+[[server]]
+name = "a"
+ip = "127.0.0.1"
+groups = []
+"#;
+
+    fn load_config(content: &str) -> Config {
+        Config::new_from_bytes("a".to_string(), content.as_bytes()).expect("could not load config")
+    }
+
+    fn check() -> ConfigCheck {
+        load_config(CONFIG).all_checks().unwrap().remove(0)
+    }
+
+    fn req(check: &ConfigCheck, client: u64, id: u64, sent: SystemTime) -> AnalyzerEvent {
+        AnalyzerEvent::new(check.to.clone(), client, check.probe, Packet::new_req(id, sent, VERSION_2), sent)
+    }
+
+    /// the response of a v2 responder that received `received_count` requests of the client.
+    fn resp(check: &ConfigCheck, client: u64, id: u64, received_count: u64, received: SystemTime) -> AnalyzerEvent {
+        let req = Packet::new_req(id, received, VERSION_2);
+        let packet = Packet::new_resp(&req, received).with_received_count(received_count);
+        AnalyzerEvent::new(check.to.clone(), client, check.probe, packet, received)
+    }
+
+    #[test]
+    fn test_remove_server_with_open_window() {
+        let check = check();
+        let mut stats = AnalyzerStats::new(load_config(CONFIG));
+        let (mut broadcast, mut events) = broadcast::channel(16);
+
+        let now = SystemTime::now();
+        stats.add_event(req(&check, 1, 1, now - Duration::from_secs(2)));
+        stats.add_event(req(&check, 1, 2, now));
+
+        // the timed out request waits for the end of the window, the other one for its response.
+        assert!(stats.slice().is_empty());
+        assert_eq!(1, stats.map.len());
+        assert_eq!(1, stats.windows.len());
+
+        stats.set_config(load_config(CONFIG_WITHOUT_B));
+        assert!(stats.map.is_empty());
+        assert!(stats.windows.is_empty());
+        assert!(stats.finished.is_empty());
+        assert!(stats.paths.is_empty());
+
+        // entries that were already sliced must not stop the analyzer.
+        let entry = AnalyzerStatsEntry {
+            server: check.to.clone(),
+            client: 1,
+            id: 1,
+            probe: check.probe,
+            req_time: now,
+            resp_time: None,
+            responder_duration: Duration::ZERO,
+            forward_lost: None,
+            reordered: false,
+            duplicates: 0,
+        };
+        stats.aggregate(vec![entry], &mut broadcast);
+
+        match events.try_recv() {
+            Ok(BroadcastEvent::UdpEchoAnalyzerEventServer(e)) => assert_eq!("b", e.server_to),
+            e => panic!("expected a server event, got {:?}", e),
+        }
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_checks_with_different_probe_settings() {
+        let check = check();
+        let other = ConfigCheck {
+            probe: ConfigProbe {
+                interval: check.probe.interval * 2,
+                ..check.probe
+            },
+            ..check.clone()
+        };
+        let mut stats = AnalyzerStats::new(load_config(CONFIG));
+
+        // both clients send to the same port, their events interleave.
+        let now = SystemTime::now();
+        for id in 1..=3 {
+            stats.add_event(req(&check, 1, id, now));
+            stats.add_event(req(&other, 2, id, now));
+            stats.add_event(resp(&check, 1, id, id, now));
+            stats.add_event(resp(&other, 2, id, id, now));
+        }
+
+        assert_eq!(2, stats.paths.len());
+        assert_eq!(3, stats.paths[&(check.to.clone(), check.probe)].id);
+        assert_eq!(3, stats.paths[&(other.to.clone(), other.probe)].id);
+        for entry in stats.map.values() {
+            assert!(entry.resp_time.is_some());
+            assert!(!entry.reordered);
+            assert_eq!(Some(0), entry.forward_lost);
+        }
+    }
+
+    /// forward loss of every response, the responses are `(id, received_count)`.
+    fn forward_lost(responses: &[(u64, u64)]) -> Vec<Option<u64>> {
+        let check = check();
+        let mut paths = HashMap::new();
+        responses
+            .iter()
+            .map(|(id, received_count)| {
+                AnalyzerStats::forward_lost(&mut paths, &resp(&check, 1, *id, *received_count, SystemTime::now()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_forward_lost() {
+        // in order.
+        assert_eq!(vec![Some(0); 4], forward_lost(&[(1, 1), (2, 2), (3, 3), (4, 4)]));
+
+        // the requests 3 and 4 did not reach the responder.
+        assert_eq!(vec![Some(0), Some(0), Some(2), Some(0)], forward_lost(&[(1, 1), (2, 2), (5, 3), (6, 4)]));
+
+        // reordered, the older response has a lower count but is no restart.
+        assert_eq!(vec![Some(0), Some(0), Some(0), Some(0)], forward_lost(&[(1, 1), (3, 3), (2, 2), (4, 4)]));
+
+        // the responder restarted before 4, the loss until then is unknown.
+        assert_eq!(vec![Some(0), Some(0), None, Some(0), Some(1)], forward_lost(&[(1, 1), (2, 2), (4, 1), (5, 2), (7, 3)]));
+
+        // the client table of the responder was full, it did not count until there was room.
+        assert_eq!(vec![None, None, None, Some(0), Some(1)], forward_lost(&[(1, 0), (2, 0), (3, 1), (4, 2), (6, 3)]));
+        assert_eq!(vec![Some(0), None, None, Some(0)], forward_lost(&[(1, 1), (2, 0), (4, 1), (5, 2)]));
+    }
+
+    #[test]
+    fn test_return_loss() {
+        let check = check();
+        let mut stats = AnalyzerStats::new(load_config(CONFIG));
+
+        // 3 did not reach the responder, the response to 5 was lost.
+        let sent = SystemTime::now() - Duration::from_secs(2);
+        for id in 1..=6 {
+            stats.add_event(req(&check, 1, id, sent));
+        }
+        for (id, received_count) in [(1, 1), (2, 2), (4, 3), (6, 5)] {
+            stats.add_event(resp(&check, 1, id, received_count, sent));
+        }
+
+        let e = aggregate_window(&mut stats, &check);
+        assert_eq!(6, e.req_count);
+        assert_eq!(4, e.resp_count);
+        assert_eq!(2, e.loss);
+        assert_eq!(Some(1), e.loss_forward);
+        assert_eq!(Some(1), e.loss_return);
+    }
+
+    /// aggregates everything that timed out, the window is not waited for.
+    fn aggregate_window(stats: &mut AnalyzerStats, check: &ConfigCheck) -> UdpEchoAnalyzerEventServer {
+        let (mut broadcast, mut events) = broadcast::channel(16);
+
+        assert!(stats.slice().is_empty());
+        let entries = ::std::mem::take(&mut stats.windows.get_mut(&check.probe.window).unwrap().entries);
+        stats.aggregate(entries, &mut broadcast);
+
+        match events.try_recv() {
+            Ok(BroadcastEvent::UdpEchoAnalyzerEventServer(e)) => e,
+            e => panic!("expected a server event, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_reordered_duplicated_and_late() {
+        let check = check();
+        let mut stats = AnalyzerStats::new(load_config(CONFIG));
+
+        let sent = SystemTime::now() - Duration::from_secs(2);
+        for id in 1..=4 {
+            stats.add_event(req(&check, 1, id, sent));
+        }
+        for id in [1, 3, 2, 3] {
+            stats.add_event(resp(&check, 1, id, id, sent));
+        }
+        // a response to a request of another client is ignored.
+        stats.add_event(resp(&check, 2, 1, 1, sent));
+
+        let e = aggregate_window(&mut stats, &check);
+        assert_eq!((4, 3, 1), (e.req_count, e.resp_count, e.loss));
+        assert_eq!((2, 1, 1, 0), (e.in_order, e.reordered, e.duplicates, e.late));
+
+        // 4 timed out, its responses are reported with the next window of the path.
+        stats.add_event(resp(&check, 1, 4, 4, sent));
+        stats.add_event(resp(&check, 1, 4, 4, sent));
+        stats.add_event(resp(&check, 1, 1, 1, sent));
+        stats.add_event(req(&check, 1, 5, sent));
+        stats.add_event(resp(&check, 1, 5, 5, sent));
+
+        let e = aggregate_window(&mut stats, &check);
+        assert_eq!((1, 1, 0), (e.req_count, e.resp_count, e.loss));
+        assert_eq!((1, 0, 2, 1), (e.in_order, e.reordered, e.duplicates, e.late));
+
+        // reported once.
+        stats.add_event(req(&check, 1, 6, sent));
+        let e = aggregate_window(&mut stats, &check);
+        assert_eq!((0, 0), (e.duplicates, e.late));
+    }
+
+    #[test]
+    fn test_label_latency() {
+        let config = format!("aggregate_labels = [\"provider\"]\n{}", CONFIG.replace("datacenter = \"fra\"", "labels = { provider = \"p1\" }"));
+        let check = load_config(&config).all_checks().unwrap().remove(0);
+        let mut stats = AnalyzerStats::new(load_config(&config));
+        let (mut broadcast, mut events) = broadcast::channel(16);
+
+        let sent = SystemTime::now() - Duration::from_secs(2);
+        for (id, latency) in [(1, 1000), (2, 3000)] {
+            stats.add_event(req(&check, 1, id, sent));
+            stats.add_event(resp(&check, 1, id, id, sent + Duration::from_micros(latency)));
+        }
+
+        assert!(stats.slice().is_empty());
+        let entries = ::std::mem::take(&mut stats.windows.get_mut(&check.probe.window).unwrap().entries);
+        stats.aggregate(entries, &mut broadcast);
+
+        let label = loop {
+            match events.try_recv() {
+                Ok(BroadcastEvent::UdpEchoAnalyzerEventLabel(e)) => break e,
+                Ok(_) => continue,
+                Err(e) => panic!("expected a label event, got {:?}", e),
+            }
+        };
+        assert_eq!(("provider", "p1"), (label.label.as_str(), label.value.as_str()));
+        assert_eq!(Some(2000), label.avg_latency);
+        // the highest value of the bucket of 1000.
+        assert_eq!(Some(1023), label.p50_latency);
+        assert_eq!(Some(3000), label.p99_latency);
+        assert_eq!(Some(125), label.jitter);
+    }
 }
@@ -1,4 +1,5 @@
 use chrono::{DateTime, Local};
+use std::collections::BTreeMap;
 
 #[derive(Clone, Debug)]
 pub struct UdpEchoAnalyzerEventServer {
@@ -6,10 +7,35 @@ pub struct UdpEchoAnalyzerEventServer {
     pub server_from: String,
     pub server_to: String,
     pub server_to_ip: String,
-    pub req_count: u16,
-    pub resp_count: u16,
+    pub server_to_port: u16,
+    pub server_to_interface: String,
+    pub server_to_labels: BTreeMap<String, String>,
+    pub req_count: u64,
+    pub resp_count: u64,
+    /// `req_count - resp_count`, late responses are lost.
+    pub loss: u64,
+    pub loss_percent: f64,
     pub max_latency: Option<u128>,
     pub min_latency: Option<u128>,
+    /// lost requests, `None` if the responder does not count received requests (v1) or nothing was answered.
+    pub loss_forward: Option<u64>,
+    /// lost responses, see `loss_forward`.
+    pub loss_return: Option<u64>,
+    /// responses in the order of the requests, `in_order + reordered = resp_count`.
+    pub in_order: u64,
+    pub reordered: u64,
+    /// copies of responses.
+    pub duplicates: u64,
+    /// responses after the timeout, their requests are counted as lost in the window of the request.
+    pub late: u64,
+    /// latencies in µs, percentiles are exact to about 6%.
+    pub avg_latency: Option<u128>,
+    pub p50_latency: Option<u128>,
+    pub p90_latency: Option<u128>,
+    pub p99_latency: Option<u128>,
+    /// interarrival jitter of RFC 3550 in µs.
+    pub jitter: Option<u128>,
+    pub rolling: UdpEchoRollingStats,
 }
 
 #[derive(Clone, Debug)]
@@ -18,8 +44,85 @@ pub struct UdpEchoAnalyzerEventDatacenter {
     pub server_from: String,
     pub datacenter_from: String,
     pub datacenter_to: String,
-    pub req_count: u16,
-    pub resp_count: u16,
+    pub req_count: u64,
+    pub resp_count: u64,
+    pub loss: u64,
+    pub loss_percent: f64,
     pub max_latency: Option<u128>,
     pub min_latency: Option<u128>,
+    /// see `UdpEchoAnalyzerEventServer`.
+    pub in_order: u64,
+    pub reordered: u64,
+    pub duplicates: u64,
+    pub late: u64,
+    pub avg_latency: Option<u128>,
+    pub p50_latency: Option<u128>,
+    pub p90_latency: Option<u128>,
+    pub p99_latency: Option<u128>,
+    pub jitter: Option<u128>,
+    pub rolling: UdpEchoRollingStats,
+}
+
+/// loss in percent and mean latency in µs over the last 1, 5 and 15 minutes, like the load average.
+/// `None` until a request was sent or a response was received.
+#[derive(Clone, Debug, Default)]
+pub struct UdpEchoRollingStats {
+    pub loss_percent_1m: Option<f64>,
+    pub loss_percent_5m: Option<f64>,
+    pub loss_percent_15m: Option<f64>,
+    pub avg_latency_1m: Option<u128>,
+    pub avg_latency_5m: Option<u128>,
+    pub avg_latency_15m: Option<u128>,
+}
+
+/// all targets with the same value of a label, see `aggregate_labels`.
+#[derive(Clone, Debug)]
+pub struct UdpEchoAnalyzerEventLabel {
+    pub date_time: DateTime<Local>,
+    pub server_from: String,
+    pub label: String,
+    pub value: String,
+    pub req_count: u64,
+    pub resp_count: u64,
+    pub loss: u64,
+    pub loss_percent: f64,
+    pub max_latency: Option<u128>,
+    pub min_latency: Option<u128>,
+    /// see `UdpEchoAnalyzerEventServer`.
+    pub avg_latency: Option<u128>,
+    pub p50_latency: Option<u128>,
+    pub p90_latency: Option<u128>,
+    pub p99_latency: Option<u128>,
+    pub jitter: Option<u128>,
+}
+
+/// counters of an echo server since the last event.
+#[derive(Clone, Debug)]
+pub struct UdpEchoServerStats {
+    pub date_time: DateTime<Local>,
+    pub listener: String,
+    pub received: u64,
+    pub answered: u64,
+    pub malformed: u64,
+    pub unauthenticated: u64,
+    pub rejected: u64,
+    pub rate_limited: u64,
+    /// answered requests per key id, shows which keys peers still use.
+    pub key_ids: BTreeMap<u16, u64>,
+}
+
+impl UdpEchoServerStats {
+    pub fn new(listener: String) -> Self {
+        Self {
+            date_time: Local::now(),
+            listener,
+            received: 0,
+            answered: 0,
+            malformed: 0,
+            unauthenticated: 0,
+            rejected: 0,
+            rate_limited: 0,
+            key_ids: BTreeMap::new(),
+        }
+    }
 }
@@ -1,68 +1,106 @@
 use crate::udp_echo::analyzer::AnalyzerEvent;
-use crate::udp_echo::packet::Packet;
+use crate::udp_echo::key::PacketKeys;
+use crate::udp_echo::packet::{Packet, PacketType, VERSION_1, VERSION_2};
 use futures::channel::mpsc::Sender;
 use futures::future;
 use mehsh_common::config::ConfigCheck;
 use std::net::SocketAddr;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use tokio;
 use tokio::net::UdpSocket;
-use tokio::task;
 use tokio::time;
 
+/// how often a client that switched to v1 tries v2 again, e.g. after the responder was updated.
+const VERSION_RETRY_INTERVAL: Duration = Duration::from_secs(600);
+
+/// how often dropped packets are reported, a flood of spoofed packets must not flood the log.
+const DROPS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// ids of started clients, the analyzer tells a restarted client from the old one with it.
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
 pub struct Client {
+    id: u64,
     check: ConfigCheck,
     remote_socket: SocketAddr,
     client_analyzer_sender: Sender<AnalyzerEvent>,
     host: String,
+    keys: Option<PacketKeys>,
 }
 
 impl Client {
     pub async fn new(
         check: ConfigCheck,
         client_analyzer_sender: Sender<AnalyzerEvent>,
+        keys: Option<PacketKeys>,
     ) -> Result<Self, ::anyhow::Error> {
-        let host = format!("{}:4232", check.to.ip.to_string());
-        let remote_socket: SocketAddr = host.parse()?;
+        let remote_socket: SocketAddr = check.to.ip.to_socket_addr(check.probe.port);
+        let host = remote_socket.to_string();
         Ok(Client {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
             check,
             remote_socket,
             client_analyzer_sender,
             host,
+            keys,
         })
     }
 
     pub async fn run(self) -> Result<(), ::anyhow::Error> {
         let remote_socket = self.remote_socket;
-        let local_socket: SocketAddr = "0.0.0.0:0".parse()?;
+        let local_socket: SocketAddr = if remote_socket.is_ipv6() {
+            "[::]:0".parse()?
+        } else {
+            "0.0.0.0:0".parse()?
+        };
 
         let socket = UdpSocket::bind(local_socket).await?;
 
         let socket_recv = Arc::new(socket);
         let socket_send = socket_recv.clone();
 
+        // v1 responders answer a v2 request with several copies of a v1 response until they sent as many bytes
+        // as they received, so the client switches to v1 requests once a v2 request gets a v1 response.
+        let version = Arc::new(ProbeVersion::default());
+        let send_version = version.clone();
+
         let mut send_client_analyzer_sender = self.client_analyzer_sender.clone();
-        let server_ident = self.check.to.identifier.clone();
-        let send_handle = task::spawn(async move {
-            let mut interval = time::interval(Duration::from_millis(25));
+        let server_ident = self.check.to.clone();
+        let probe = self.check.probe;
+        let send_keys = self.keys.clone();
+        let client_id = self.id;
+        // both halves run in the task of the client, aborting the client stops both.
+        let send_handle = Box::pin(async move {
+            let mut interval = time::interval(probe.interval);
 
             let mut counter: u64 = 0;
+            let mut drops = ClientDrops::new();
 
             loop {
+                drops.report(&remote_socket);
                 counter = counter + 1;
 
-                let packet = Packet::new_req(counter);
+                let version = send_version.request_version(counter);
+
+                let sent = SystemTime::now();
+                let packet = Packet::new_req(counter, sent, version);
 
                 match send_client_analyzer_sender
-                    .try_send(AnalyzerEvent::new(server_ident.clone(), packet.clone()))
+                    .try_send(AnalyzerEvent::new(server_ident.clone(), client_id, probe, packet.clone(), sent))
                 {
                     Ok(_) => {}
-                    Err(_e) => eprintln!("issue with the client_send_handle"),
+                    Err(_e) => drops.analyzer_full += 1,
+                };
+
+                let data = match &send_keys {
+                    Some(keys) => keys.sign(packet.to_bytes()),
+                    None => packet.to_bytes(),
                 };
 
                 match socket_send
-                    .send_to(&packet.to_bytes(), &remote_socket)
+                    .send_to(&data, &remote_socket)
                     .await
                 {
                     Ok(_) => {}
@@ -74,36 +112,67 @@ impl Client {
         });
 
         let mut recv_client_analyzer_sender = self.client_analyzer_sender.clone();
-        let recv_ident = self.check.to.identifier.clone();
-        let recv_handle = task::spawn(async move {
+        let recv_ident = self.check.to.clone();
+        let recv_keys = self.keys;
+        let recv_handle = Box::pin(async move {
             let mut data = vec![0u8; 100];
+            let mut drops = ClientDrops::new();
 
             loop {
-                let len = match socket_recv.recv(&mut data).await {
-                    Ok(l) => l,
+                drops.report(&remote_socket);
+
+                let (len, source) = match socket_recv.recv_from(&mut data).await {
+                    Ok(r) => r,
                     Err(_e) => {
                         eprintln!("could not recv socket {:?}", &socket_recv);
                         continue;
                     }
                 };
+                let received = SystemTime::now();
 
-                let packet = match Packet::new_from_raw(&data[0..len]) {
-                    Ok(p) => p,
+                // anyone can send to the socket, only responses of the server are counted.
+                if source != remote_socket {
+                    drops.source += 1;
+                    continue;
+                }
+
+                let packet_data = match &recv_keys {
+                    None => &data[0..len],
+                    Some(keys) => match keys.verify(&data[0..len]) {
+                        Ok((_, d)) => d,
+                        Err(_) => {
+                            drops.unauthenticated += 1;
+                            continue;
+                        }
+                    },
+                };
+
+                let packet = match Packet::new_from_raw(packet_data) {
+                    Ok(p) if p.get_type() == &PacketType::Resp => p,
+                    Ok(_) => {
+                        drops.not_response += 1;
+                        continue;
+                    }
                     Err(_) => {
-                        eprintln!(
-                            "could not parse package {:?}, {:?}",
-                            &socket_recv,
-                            &data[0..len]
-                        );
+                        drops.malformed += 1;
                         continue;
                     }
                 };
 
+                if version.update(&packet) {
+                    println!(
+                        "client: {} answers with v{}, sending v{} probes",
+                        remote_socket,
+                        packet.get_version(),
+                        packet.get_version()
+                    );
+                }
+
                 match recv_client_analyzer_sender
-                    .try_send(AnalyzerEvent::new(recv_ident.clone(), packet.clone()))
+                    .try_send(AnalyzerEvent::new(recv_ident.clone(), client_id, probe, packet.clone(), received))
                 {
                     Ok(_) => {}
-                    Err(_e) => eprintln!("issue with the client_send_handle"),
+                    Err(_e) => drops.analyzer_full += 1,
                 };
 
                 // println!("client recv {:?}", &packet);
@@ -115,3 +184,178 @@ impl Client {
         Ok(())
     }
 }
+
+/// packets and events the client dropped since the last report, each half of the client counts its own.
+struct ClientDrops {
+    /// not sent by the server.
+    source: u64,
+    unauthenticated: u64,
+    not_response: u64,
+    malformed: u64,
+    /// requests and responses the analyzer did not get because its channel was full.
+    analyzer_full: u64,
+    reported: Instant,
+}
+
+impl ClientDrops {
+    fn new() -> Self {
+        Self {
+            source: 0,
+            unauthenticated: 0,
+            not_response: 0,
+            malformed: 0,
+            analyzer_full: 0,
+            reported: Instant::now(),
+        }
+    }
+
+    /// at most once per `DROPS_INTERVAL`, called on every sent or received packet.
+    fn report(&mut self, remote_socket: &SocketAddr) {
+        if self.reported.elapsed() < DROPS_INTERVAL {
+            return;
+        }
+
+        let counts = [
+            ("wrong source", self.source),
+            ("unauthenticated", self.unauthenticated),
+            ("not a response", self.not_response),
+            ("malformed", self.malformed),
+            ("analyzer busy", self.analyzer_full),
+        ]
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(name, count)| format!("{} {}", name, count))
+        .collect::<Vec<_>>();
+
+        if !counts.is_empty() {
+            eprintln!("client: dropped packets for {}, {}", remote_socket, counts.join(", "));
+        }
+
+        *self = Self::new();
+    }
+}
+
+/// the version the requests of a client are sent with, shared by the send and the receive half.
+struct ProbeVersion {
+    version: AtomicU32,
+    /// id of the last request.
+    last_id: AtomicU64,
+    /// requests from this id on were sent as v2, v1 responses to earlier requests do not mean the responder only knows v1.
+    v2_from_id: AtomicU64,
+    /// when v2 was tried the last time, set when the client switches to v1. `None` if v2 is due.
+    retried: Mutex<Option<Instant>>,
+}
+
+impl Default for ProbeVersion {
+    fn default() -> Self {
+        Self {
+            version: AtomicU32::new(VERSION_2),
+            last_id: AtomicU64::new(0),
+            v2_from_id: AtomicU64::new(0),
+            retried: Mutex::new(None),
+        }
+    }
+}
+
+impl ProbeVersion {
+    /// the version of the request with the id, a v1 client tries v2 once per `VERSION_RETRY_INTERVAL`.
+    fn request_version(&self, id: u64) -> u32 {
+        self.last_id.store(id, Ordering::Relaxed);
+
+        match self.version.load(Ordering::Relaxed) {
+            VERSION_1 => {
+                let mut retried = self.retried.lock().expect("lock is never poisoned");
+                match *retried {
+                    Some(retried) if retried.elapsed() < VERSION_RETRY_INTERVAL => VERSION_1,
+                    _ => {
+                        *retried = Some(Instant::now());
+                        VERSION_2
+                    }
+                }
+            }
+            version => version,
+        }
+    }
+
+    /// true if the client switched the version.
+    fn update(&self, response: &Packet) -> bool {
+        let current = self.version.load(Ordering::Relaxed);
+        match (current, response.get_version()) {
+            (VERSION_2, VERSION_1) if response.get_id() >= self.v2_from_id.load(Ordering::Relaxed) => {
+                *self.retried.lock().expect("lock is never poisoned") = Some(Instant::now());
+                self.version.store(VERSION_1, Ordering::Relaxed);
+                true
+            }
+            (VERSION_1, VERSION_2) => {
+                self.v2_from_id.store(self.last_id.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
+                self.version.store(VERSION_2, Ordering::Relaxed);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resp(id: u64, version: u32) -> Packet {
+        Packet::new_resp(&Packet::new_req(id, SystemTime::now(), version), SystemTime::now())
+    }
+
+    /// as if the last retry was before the retry interval.
+    fn retry_due(version: &ProbeVersion) {
+        *version.retried.lock().unwrap() = None;
+    }
+
+    #[test]
+    fn test_probe_version() {
+        let version = ProbeVersion::default();
+        assert_eq!(VERSION_2, version.request_version(1));
+        assert!(!version.update(&resp(1, VERSION_2)));
+
+        // the responder only knows v1.
+        assert_eq!(VERSION_2, version.request_version(2));
+        assert!(version.update(&resp(2, VERSION_1)));
+        // the other copies of the response.
+        assert!(!version.update(&resp(2, VERSION_1)));
+        assert_eq!(VERSION_1, version.request_version(3));
+        assert!(!version.update(&resp(3, VERSION_1)));
+
+        // one v2 request per retry interval, the responder still only knows v1.
+        retry_due(&version);
+        assert_eq!(VERSION_2, version.request_version(4));
+        assert_eq!(VERSION_1, version.request_version(5));
+        assert!(!version.update(&resp(4, VERSION_1)));
+
+        // the responder was updated.
+        retry_due(&version);
+        assert_eq!(VERSION_2, version.request_version(6));
+        assert_eq!(VERSION_1, version.request_version(7));
+        assert!(version.update(&resp(6, VERSION_2)));
+        assert_eq!(VERSION_2, version.request_version(8));
+
+        // the response to the v1 request that was still in flight is no reason to switch back.
+        assert!(!version.update(&resp(7, VERSION_1)));
+        assert_eq!(VERSION_2, version.request_version(9));
+
+        // a v1 response to a later v2 request is.
+        assert!(version.update(&resp(8, VERSION_1)));
+        assert_eq!(VERSION_1, version.request_version(10));
+    }
+
+    #[test]
+    fn test_probe_version_retry_after_switch() {
+        // a client that runs for longer than the retry interval before it switches to v1.
+        let version = ProbeVersion::default();
+        retry_due(&version);
+        assert_eq!(VERSION_2, version.request_version(1));
+        assert!(version.update(&resp(1, VERSION_1)));
+
+        // the retry interval starts with the switch.
+        for id in 2..10 {
+            assert_eq!(VERSION_1, version.request_version(id));
+        }
+    }
+}
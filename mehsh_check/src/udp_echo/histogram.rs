@@ -0,0 +1,230 @@
+use std::convert::TryFrom;
+
+/// sub buckets per power of two, the error of a percentile is at most 1 / SUB_BUCKETS.
+const SUB_BUCKETS: u64 = 16;
+const SUB_BUCKET_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+
+/// latencies above 2^36 µs (~19h) are counted in the last bucket.
+const MAX_BITS: u32 = 36;
+const BUCKETS: usize = ((MAX_BITS - SUB_BUCKET_BITS + 1) as u64 * SUB_BUCKETS) as usize;
+
+/// latencies in µs in log linear buckets, the size does not depend on the number of latencies.
+#[derive(Clone, Debug)]
+pub struct LatencyHistogram {
+    buckets: Vec<u32>,
+    count: u64,
+    sum: u128,
+    max: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; BUCKETS],
+            count: 0,
+            sum: 0,
+            max: 0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    fn bucket(value: u64) -> usize {
+        if value < SUB_BUCKETS {
+            return value as usize;
+        }
+
+        let bits = (64 - value.leading_zeros()).min(MAX_BITS);
+        let shift = bits - SUB_BUCKET_BITS - 1;
+        let sub_bucket = (value >> shift).min(2 * SUB_BUCKETS - 1) - SUB_BUCKETS;
+
+        ((shift + 1) as u64 * SUB_BUCKETS + sub_bucket) as usize
+    }
+
+    /// the highest value of the bucket.
+    fn bucket_value(bucket: usize) -> u64 {
+        let bucket = bucket as u64;
+        if bucket < SUB_BUCKETS {
+            return bucket;
+        }
+
+        let shift = bucket / SUB_BUCKETS - 1;
+        let sub_bucket = bucket % SUB_BUCKETS + SUB_BUCKETS;
+
+        ((sub_bucket + 1) << shift) - 1
+    }
+
+    pub fn add(&mut self, value: u128) {
+        let value = u64::try_from(value).unwrap_or(u64::MAX);
+        self.buckets[Self::bucket(value)] += 1;
+        self.count += 1;
+        self.sum += value as u128;
+        self.max = self.max.max(value);
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (bucket, count) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *bucket += count;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn mean(&self) -> Option<u128> {
+        match self.count {
+            0 => None,
+            count => Some(self.sum / count as u128),
+        }
+    }
+
+    /// `percentile` between 0 and 100, never above the highest latency.
+    pub fn percentile(&self, percentile: u64) -> Option<u128> {
+        if self.count == 0 {
+            return None;
+        }
+
+        // the rank of the value, at least the first value.
+        let rank = (self.count * percentile).div_ceil(100).max(1);
+
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += *count as u64;
+            if seen >= rank {
+                return Some(Self::bucket_value(bucket).min(self.max) as u128);
+            }
+        }
+
+        None
+    }
+}
+
+/// interarrival jitter of RFC 3550, the round trip times of consecutive responses replace the transit times.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Jitter {
+    jitter: f64,
+    last_latency: Option<u128>,
+    samples: u64,
+}
+
+impl Jitter {
+    pub fn add(&mut self, latency: u128) {
+        if let Some(last) = self.last_latency {
+            let d = (latency as f64 - last as f64).abs();
+            self.jitter += (d - self.jitter) / 16.0;
+        }
+        self.last_latency = Some(latency);
+        self.samples += 1;
+    }
+
+    /// in µs, `None` until two responses were received.
+    pub fn get(&self) -> Option<u128> {
+        match self.samples {
+            0 | 1 => None,
+            _ => Some(self.jitter.round() as u128),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket() {
+        assert_eq!(528, BUCKETS);
+        assert_eq!(0, LatencyHistogram::bucket(0));
+        assert_eq!(15, LatencyHistogram::bucket(15));
+        assert_eq!(16, LatencyHistogram::bucket(16));
+        assert_eq!(31, LatencyHistogram::bucket(31));
+        assert_eq!(32, LatencyHistogram::bucket(32));
+        assert_eq!(BUCKETS - 1, LatencyHistogram::bucket(1 << MAX_BITS));
+        assert_eq!(BUCKETS - 1, LatencyHistogram::bucket(u64::MAX));
+
+        let mut histogram = LatencyHistogram::default();
+        histogram.add(u128::MAX);
+        assert_eq!(1, histogram.buckets[BUCKETS - 1]);
+        // reported as the highest value of the last bucket.
+        assert_eq!(Some((1 << MAX_BITS) - 1), histogram.percentile(100));
+    }
+
+    #[test]
+    fn test_bucket_value() {
+        let mut values: Vec<u64> = (0..1024).collect();
+        for bits in 10..MAX_BITS {
+            values.extend([(1 << bits) - 1, 1 << bits, (1 << bits) + 1, 3 << (bits - 1)]);
+        }
+
+        for value in values {
+            let bucket_value = LatencyHistogram::bucket_value(LatencyHistogram::bucket(value));
+            assert!(bucket_value >= value, "{} below {}", bucket_value, value);
+            assert!(bucket_value - value <= value / SUB_BUCKETS, "{} too far above {}", bucket_value, value);
+        }
+    }
+
+    #[test]
+    fn test_percentile() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(None, histogram.percentile(50));
+        assert_eq!(None, histogram.mean());
+
+        histogram.add(1234);
+        assert_eq!(Some(1234), histogram.percentile(0));
+        assert_eq!(Some(1234), histogram.percentile(50));
+        assert_eq!(Some(1234), histogram.percentile(100));
+
+        let mut histogram = LatencyHistogram::default();
+        for value in 1..=100 {
+            histogram.add(value);
+        }
+        assert_eq!(Some(50), histogram.mean());
+        assert_eq!(Some(1), histogram.percentile(1));
+        assert_eq!(Some(51), histogram.percentile(50));
+        assert_eq!(Some(91), histogram.percentile(90));
+        assert_eq!(Some(99), histogram.percentile(99));
+        // the bucket of 100 ends at 103.
+        assert_eq!(Some(100), histogram.percentile(100));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut all = LatencyHistogram::default();
+        let mut low = LatencyHistogram::default();
+        let mut high = LatencyHistogram::default();
+        for value in 1..=100 {
+            all.add(value);
+            match value <= 50 {
+                true => low.add(value),
+                false => high.add(value),
+            }
+        }
+
+        low.merge(&high);
+        assert_eq!(all.buckets, low.buckets);
+        assert_eq!(all.count, low.count);
+        assert_eq!(all.sum, low.sum);
+        assert_eq!(all.max, low.max);
+        assert_eq!(Some(51), low.percentile(50));
+
+        low.merge(&LatencyHistogram::default());
+        assert_eq!(all.count, low.count);
+    }
+
+    #[test]
+    fn test_jitter() {
+        let mut jitter = Jitter::default();
+        assert_eq!(None, jitter.get());
+
+        jitter.add(100);
+        assert_eq!(None, jitter.get());
+
+        // J(i) = J(i-1) + (|D(i-1,i)| - J(i-1)) / 16
+        jitter.add(120);
+        assert_eq!(1.25, jitter.jitter);
+        jitter.add(110);
+        assert_eq!(1.796875, jitter.jitter);
+        jitter.add(150);
+        assert_eq!(4.1845703125, jitter.jitter);
+        assert_eq!(Some(4), jitter.get());
+    }
+}
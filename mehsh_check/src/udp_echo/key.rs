@@ -0,0 +1,247 @@
+use anyhow::{anyhow, Context};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// environment variable that holds the keys if no key file is given.
+pub const KEY_ENV: &str = "MEHSH_KEY";
+
+/// bytes of the hmac that are appended to every packet.
+pub const TAG_SIZE: usize = 16;
+
+/// bytes of the key id that precede the tag.
+pub const KEY_ID_SIZE: usize = 2;
+
+const MIN_KEY_SIZE: usize = 16;
+
+/// shared secret, packets are signed with a truncated hmac-sha256.
+#[derive(Clone)]
+pub struct PacketKey {
+    id: u16,
+    key: Vec<u8>,
+}
+
+impl fmt::Debug for PacketKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // never log the secret.
+        write!(f, "PacketKey({})", self.id)
+    }
+}
+
+impl PacketKey {
+    pub fn new(id: u16, key: &[u8]) -> Result<Self, ::anyhow::Error> {
+        if key.len() < MIN_KEY_SIZE {
+            return Err(anyhow!("key {} must have at least {} bytes", id, MIN_KEY_SIZE));
+        }
+
+        Ok(Self { id, key: key.to_vec() })
+    }
+
+    pub fn get_id(&self) -> u16 {
+        self.id
+    }
+
+    fn mac(&self, data: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("hmac accepts keys of any size");
+        mac.update(data);
+        mac
+    }
+}
+
+/// the primary key packets are signed with and older keys that are still accepted, for key rotation.
+#[derive(Clone, Debug)]
+pub struct PacketKeys {
+    /// the first key is the primary key.
+    keys: Vec<PacketKey>,
+}
+
+impl PacketKeys {
+    /// one key per line as `id:key`, the first line is the primary key, `#` starts a comment.
+    /// a single line without an id is key 0.
+    pub fn parse(data: &str) -> Result<Self, ::anyhow::Error> {
+        let lines = data
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .collect::<Vec<_>>();
+
+        let mut keys: Vec<PacketKey> = vec![];
+        for line in &lines {
+            let key = match line.split_once(':').map(|(id, key)| (id.parse::<u16>(), key)) {
+                Some((Ok(id), key)) => PacketKey::new(id, key.trim().as_bytes())?,
+                _ if lines.len() == 1 => PacketKey::new(0, line.as_bytes())?,
+                _ => return Err(anyhow!("expected 'id:key', e.g. '2:secret', if there is more than one key")),
+            };
+
+            if keys.iter().any(|k| k.id == key.id) {
+                return Err(anyhow!("key id {} is used twice", key.id));
+            }
+
+            keys.push(key);
+        }
+
+        if keys.is_empty() {
+            return Err(anyhow!("no key found"));
+        }
+
+        Ok(Self { keys })
+    }
+
+    /// reads the keys from the file or from `MEHSH_KEY`, without both packets are not signed.
+    pub fn load(key_file: Option<&Path>) -> Result<Option<Self>, ::anyhow::Error> {
+        let data = match key_file {
+            Some(path) => {
+                fs::read_to_string(path).with_context(|| format!("could not read key file {}", path.display()))?
+            }
+            None => match ::std::env::var(KEY_ENV) {
+                Ok(data) => data,
+                Err(_) => return Ok(None),
+            },
+        };
+
+        Self::parse(&data).map(Some)
+    }
+
+    pub fn primary(&self) -> &PacketKey {
+        &self.keys[0]
+    }
+
+    /// ids of the older keys that are still accepted.
+    pub fn accepted_ids(&self) -> Vec<u16> {
+        self.keys[1..].iter().map(|k| k.id).collect()
+    }
+
+    fn get(&self, id: u16) -> Option<&PacketKey> {
+        self.keys.iter().find(|k| k.id == id)
+    }
+
+    /// appends the key id and the tag of the primary key to the packet.
+    pub fn sign(&self, data: Vec<u8>) -> Vec<u8> {
+        Self::sign_with_key(self.primary(), data)
+    }
+
+    /// signs with the key of the id if it is known, otherwise with the primary key.
+    /// responses use the key of the request, so peers that did not switch to the new primary key yet accept them.
+    pub fn sign_with(&self, id: u16, data: Vec<u8>) -> Vec<u8> {
+        Self::sign_with_key(self.get(id).unwrap_or_else(|| self.primary()), data)
+    }
+
+    fn sign_with_key(key: &PacketKey, mut data: Vec<u8>) -> Vec<u8> {
+        data.extend_from_slice(&key.id.to_be_bytes());
+        let tag = key.mac(&data).finalize().into_bytes();
+        data.extend_from_slice(&tag[..TAG_SIZE]);
+        data
+    }
+
+    /// the key id and the packet without key id and tag, if the tag is valid.
+    pub fn verify<'a>(&self, data: &'a [u8]) -> Result<(u16, &'a [u8]), ::anyhow::Error> {
+        if data.len() < KEY_ID_SIZE + TAG_SIZE {
+            return Err(anyhow!("packet is too short for a signature"));
+        }
+
+        let (signed, tag) = data.split_at(data.len() - TAG_SIZE);
+        let (packet, id) = signed.split_at(signed.len() - KEY_ID_SIZE);
+        let id = u16::from_be_bytes([id[0], id[1]]);
+
+        let key = self.get(id).ok_or_else(|| anyhow!("unknown key id {}", id))?;
+
+        key.mac(signed)
+            .verify_truncated_left(tag)
+            .map_err(|_| anyhow!("invalid packet signature"))?;
+
+        Ok((id, packet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: &str = "0123456789abcdef";
+    const KEY_B: &str = "fedcba9876543210";
+
+    #[test]
+    fn test_parse() {
+        let keys = PacketKeys::parse(&format!("# comment\n  {}  \n", KEY_A)).unwrap();
+        assert_eq!(0, keys.primary().get_id());
+        assert_eq!(KEY_A.as_bytes(), keys.primary().key.as_slice());
+        assert!(keys.accepted_ids().is_empty());
+
+        // a single `id:key` line.
+        let keys = PacketKeys::parse(&format!("7:{}", KEY_A)).unwrap();
+        assert_eq!(7, keys.primary().get_id());
+
+        let keys = PacketKeys::parse(&format!("2:{}\n\n# old key\n1: {}\n", KEY_B, KEY_A)).unwrap();
+        assert_eq!(2, keys.primary().get_id());
+        assert_eq!(KEY_B.as_bytes(), keys.primary().key.as_slice());
+        assert_eq!(vec![1], keys.accepted_ids());
+
+        // lines without id are only allowed for a single key.
+        assert!(PacketKeys::parse(&format!("2:{}\n{}", KEY_B, KEY_A)).is_err());
+        assert!(PacketKeys::parse("# no key\n\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_duplicate_id() {
+        let err = PacketKeys::parse(&format!("1:{}\n1:{}", KEY_A, KEY_B)).unwrap_err();
+        assert_eq!("key id 1 is used twice", err.to_string());
+    }
+
+    #[test]
+    fn test_parse_short_key() {
+        assert!(PacketKeys::parse("short").is_err());
+        assert!(PacketKeys::parse(&format!("1:{}\n2:short", KEY_A)).is_err());
+        assert!(PacketKeys::parse(&KEY_A[1..]).is_err());
+        assert!(PacketKey::new(0, KEY_A.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let keys = PacketKeys::parse(&format!("2:{}\n1:{}", KEY_B, KEY_A)).unwrap();
+        let old_keys = PacketKeys::parse(&format!("1:{}", KEY_A)).unwrap();
+
+        let signed = keys.sign(b"packet".to_vec());
+        assert_eq!(6 + KEY_ID_SIZE + TAG_SIZE, signed.len());
+        assert_eq!((2, &b"packet"[..]), keys.verify(&signed).unwrap());
+
+        // signed by a peer that still uses the older key.
+        let signed = old_keys.sign(b"packet".to_vec());
+        assert_eq!((1, &b"packet"[..]), keys.verify(&signed).unwrap());
+
+        // the response uses the key of the request.
+        let signed = keys.sign_with(1, b"packet".to_vec());
+        assert_eq!((1, &b"packet"[..]), old_keys.verify(&signed).unwrap());
+        let signed = keys.sign_with(9, b"packet".to_vec());
+        assert_eq!((2, &b"packet"[..]), keys.verify(&signed).unwrap());
+    }
+
+    #[test]
+    fn test_verify_reject() {
+        let keys = PacketKeys::parse(&format!("2:{}\n1:{}", KEY_B, KEY_A)).unwrap();
+        let signed = keys.sign(b"packet".to_vec());
+
+        // unknown key id.
+        let other_keys = PacketKeys::parse(&format!("3:{}", KEY_A)).unwrap();
+        let err = keys.verify(&other_keys.sign(b"packet".to_vec())).unwrap_err();
+        assert_eq!("unknown key id 3", err.to_string());
+
+        // flipped tag byte.
+        let mut flipped = signed.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert_eq!("invalid packet signature", keys.verify(&flipped).unwrap_err().to_string());
+
+        // changed packet.
+        let mut changed = signed.clone();
+        changed[0] ^= 1;
+        assert!(keys.verify(&changed).is_err());
+
+        // truncated packet.
+        assert!(keys.verify(&signed[..signed.len() - 1]).is_err());
+        assert_eq!(
+            "packet is too short for a signature",
+            keys.verify(&signed[..KEY_ID_SIZE + TAG_SIZE - 1]).unwrap_err().to_string()
+        );
+    }
+}
@@ -1,5 +1,8 @@
 pub mod analyzer;
 pub mod analyzer_event;
 pub mod client;
+pub mod histogram;
+pub mod key;
 pub mod packet;
+pub mod rolling;
 pub mod server;
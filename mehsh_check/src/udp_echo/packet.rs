@@ -1,44 +1,123 @@
 use bytes::{Buf, BufMut, BytesMut};
 use std::mem::size_of;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use anyhow::anyhow;
 
 const PACKAGE_MAGIC: u32 = 326134347;
 
+/// magic, version, id and type.
 const PACKAGE_SIZE: usize =
     size_of::<u32>() + size_of::<u32>() + size_of::<u64>() + size_of::<u16>();
 
+/// v2 adds the send time of the client, the receive and send time of the responder
+/// and how many requests the responder received from the client.
+const PACKAGE_SIZE_V2: usize = PACKAGE_SIZE + 4 * size_of::<u64>();
+
+pub const VERSION_1: u32 = 1;
+pub const VERSION_2: u32 = 2;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum PacketType {
     Req,
     Resp,
 }
 
+/// microseconds since the unix epoch, only v2 packets have them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PacketTimestamps {
+    pub client_sent: u64,
+    pub responder_received: u64,
+    pub responder_sent: u64,
+}
+
+impl PacketTimestamps {
+    /// time the responder needed to answer, it is not part of the round trip time.
+    pub fn responder_duration(&self) -> Duration {
+        Duration::from_micros(self.responder_sent.saturating_sub(self.responder_received))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Packet {
     version: u32,
     id: u64,
     packet_type: PacketType,
+    timestamps: Option<PacketTimestamps>,
+    /// requests the responder received from the client, including this one. 0 if the responder does not know.
+    received_count: Option<u64>,
+    /// key the packet was signed with, not part of the packet itself, see `PacketKeys::verify`.
+    key_id: Option<u16>,
+}
+
+pub fn unix_micros(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64
 }
 
 impl Packet {
-    pub fn new_req(id: u64) -> Self {
+    /// v1 requests have no timestamps, see `Client` for the version negotiation.
+    pub fn new_req(id: u64, sent: SystemTime, version: u32) -> Self {
+        let (timestamps, received_count) = match version {
+            VERSION_1 => (None, None),
+            _ => (
+                Some(PacketTimestamps {
+                    client_sent: unix_micros(sent),
+                    ..Default::default()
+                }),
+                Some(0),
+            ),
+        };
+
         Packet {
-            version: 1,
+            version,
             id,
             packet_type: PacketType::Req,
+            timestamps,
+            received_count,
+            key_id: None,
         }
     }
 
-    pub fn new_resp(id: u64) -> Self {
+    /// the response has the version of the request, so clients that only know v1 keep working.
+    pub fn new_resp(req: &Packet, received: SystemTime) -> Self {
         Packet {
-            version: 1,
-            id,
+            version: req.version,
+            id: req.id,
             packet_type: PacketType::Resp,
+            timestamps: req.timestamps.map(|t| PacketTimestamps {
+                client_sent: t.client_sent,
+                responder_received: unix_micros(received),
+                responder_sent: 0,
+            }),
+            received_count: req.received_count.map(|_| 0),
+            key_id: None,
+        }
+    }
+
+    pub fn with_key_id(self, key_id: Option<u16>) -> Self {
+        Self { key_id, ..self }
+    }
+
+    /// ignored for v1, it has no field for it.
+    pub fn with_received_count(self, count: u64) -> Self {
+        Self {
+            received_count: self.received_count.map(|_| count),
+            ..self
+        }
+    }
+
+    /// should be set right before the response is sent.
+    pub fn with_responder_sent(self, sent: SystemTime) -> Self {
+        Self {
+            timestamps: self.timestamps.map(|t| PacketTimestamps {
+                responder_sent: unix_micros(sent),
+                ..t
+            }),
+            ..self
         }
     }
 
     pub fn to_bytes(self) -> Vec<u8> {
-        let mut buf = BytesMut::with_capacity(PACKAGE_SIZE);
+        let mut buf = BytesMut::with_capacity(PACKAGE_SIZE_V2);
         buf.put_u32(PACKAGE_MAGIC);
         buf.put_u32(self.version);
         buf.put_u64(self.id);
@@ -47,6 +126,13 @@ impl Packet {
             PacketType::Resp => 2,
         });
 
+        if let Some(timestamps) = self.timestamps {
+            buf.put_u64(timestamps.client_sent);
+            buf.put_u64(timestamps.responder_received);
+            buf.put_u64(timestamps.responder_sent);
+            buf.put_u64(self.received_count.unwrap_or(0));
+        }
+
         buf.to_vec()
     }
 
@@ -71,10 +157,27 @@ impl Packet {
             _ => return Err(anyhow!("unknown packet type")),
         };
 
+        let (timestamps, received_count) = match version {
+            VERSION_1 => (None, None),
+            VERSION_2 if data.len() == PACKAGE_SIZE_V2 => (
+                Some(PacketTimestamps {
+                    client_sent: buf.get_u64(),
+                    responder_received: buf.get_u64(),
+                    responder_sent: buf.get_u64(),
+                }),
+                Some(buf.get_u64()),
+            ),
+            VERSION_2 => return Err(anyhow!("invalid packet size")),
+            _ => return Err(anyhow!("unknown packet version {}", version)),
+        };
+
         Ok(Packet {
             version,
             id,
             packet_type,
+            timestamps,
+            received_count,
+            key_id: None,
         })
     }
 
@@ -89,4 +192,106 @@ impl Packet {
     pub fn get_type(&self) -> &PacketType {
         &self.packet_type
     }
+
+    pub fn get_timestamps(&self) -> Option<&PacketTimestamps> {
+        self.timestamps.as_ref()
+    }
+
+    /// `None` for v1 packets and if the responder does not know.
+    pub fn get_received_count(&self) -> Option<u64> {
+        self.received_count.filter(|c| *c > 0)
+    }
+
+    pub fn get_key_id(&self) -> Option<u16> {
+        self.key_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_v2() {
+        let sent = UNIX_EPOCH + Duration::from_micros(1_000);
+        let req = Packet::new_req(7, sent, VERSION_2);
+        let data = req.clone().to_bytes();
+        assert_eq!(PACKAGE_SIZE_V2, data.len());
+
+        let req = Packet::new_from_raw(&data).unwrap();
+        assert_eq!(VERSION_2, req.get_version());
+        assert_eq!(7, req.get_id());
+        assert_eq!(&PacketType::Req, req.get_type());
+        assert_eq!(1_000, req.get_timestamps().unwrap().client_sent);
+        assert_eq!(None, req.get_received_count());
+
+        let resp = Packet::new_resp(&req, UNIX_EPOCH + Duration::from_micros(2_000))
+            .with_received_count(3)
+            .with_responder_sent(UNIX_EPOCH + Duration::from_micros(2_500));
+        let resp = Packet::new_from_raw(&resp.to_bytes()).unwrap();
+        assert_eq!(VERSION_2, resp.get_version());
+        assert_eq!(7, resp.get_id());
+        assert_eq!(&PacketType::Resp, resp.get_type());
+        assert_eq!(
+            Some(&PacketTimestamps {
+                client_sent: 1_000,
+                responder_received: 2_000,
+                responder_sent: 2_500,
+            }),
+            resp.get_timestamps()
+        );
+        assert_eq!(Duration::from_micros(500), resp.get_timestamps().unwrap().responder_duration());
+        assert_eq!(Some(3), resp.get_received_count());
+
+        // the responder did not count the request.
+        let resp = Packet::new_resp(&req, SystemTime::now()).with_received_count(0);
+        assert_eq!(None, Packet::new_from_raw(&resp.to_bytes()).unwrap().get_received_count());
+    }
+
+    #[test]
+    fn test_v1() {
+        let req = Packet::new_req(7, SystemTime::now(), VERSION_1);
+        let data = req.to_bytes();
+        assert_eq!(PACKAGE_SIZE, data.len());
+
+        let req = Packet::new_from_raw(&data).unwrap();
+        assert_eq!(VERSION_1, req.get_version());
+        assert_eq!(7, req.get_id());
+        assert_eq!(None, req.get_timestamps());
+
+        // v1 requests get v1 responses without timestamps and count.
+        let resp = Packet::new_resp(&req, SystemTime::now())
+            .with_received_count(3)
+            .with_responder_sent(SystemTime::now());
+        let data = resp.to_bytes();
+        assert_eq!(PACKAGE_SIZE, data.len());
+
+        let resp = Packet::new_from_raw(&data).unwrap();
+        assert_eq!(VERSION_1, resp.get_version());
+        assert_eq!(&PacketType::Resp, resp.get_type());
+        assert_eq!(None, resp.get_timestamps());
+        assert_eq!(None, resp.get_received_count());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let data = Packet::new_req(7, SystemTime::now(), VERSION_2).to_bytes();
+
+        assert!(Packet::new_from_raw(&data[..PACKAGE_SIZE - 1]).is_err());
+        // v2 packets must have all fields.
+        assert!(Packet::new_from_raw(&data[..PACKAGE_SIZE]).is_err());
+        assert!(Packet::new_from_raw(&data[..PACKAGE_SIZE_V2 - 1]).is_err());
+
+        let mut magic = data.clone();
+        magic[0] ^= 1;
+        assert!(Packet::new_from_raw(&magic).is_err());
+
+        let mut version = data.clone();
+        version[7] = 3;
+        assert!(Packet::new_from_raw(&version).is_err());
+
+        let mut packet_type = data.clone();
+        packet_type[17] = 3;
+        assert!(Packet::new_from_raw(&packet_type).is_err());
+    }
 }
@@ -0,0 +1,176 @@
+use crate::udp_echo::analyzer_event::UdpEchoRollingStats;
+use std::time::{Duration, Instant};
+
+/// 1, 5 and 15 minutes, like the load average.
+const PERIODS: [Duration; 3] = [
+    Duration::from_secs(60),
+    Duration::from_secs(300),
+    Duration::from_secs(900),
+];
+
+/// exponentially weighted mean, older windows lose weight over the period.
+/// unlike the load average it does not start at 0, until the period is over the windows are weighted almost equally.
+#[derive(Clone, Copy, Debug, Default)]
+struct RollingAverage {
+    sum: f64,
+    weight: f64,
+}
+
+impl RollingAverage {
+    fn add(&mut self, value: f64, weight: f64, decay: f64) {
+        self.sum = self.sum * decay + value * weight;
+        self.weight = self.weight * decay + weight;
+    }
+
+    fn get(&self) -> Option<f64> {
+        match self.weight > 0.0 {
+            true => Some(self.sum / self.weight),
+            false => None,
+        }
+    }
+}
+
+/// loss and latency of a path or datacenter over the last 1, 5 and 15 minutes, the size does not depend on the number of windows.
+#[derive(Clone, Debug)]
+pub struct RollingStats {
+    /// weighted by the sent requests of the window.
+    loss_percent: [RollingAverage; 3],
+    /// weighted by the responses of the window.
+    latency: [RollingAverage; 3],
+    updated: Instant,
+}
+
+impl Default for RollingStats {
+    fn default() -> Self {
+        Self {
+            loss_percent: Default::default(),
+            latency: Default::default(),
+            updated: Instant::now(),
+        }
+    }
+}
+
+impl RollingStats {
+    pub fn add(&mut self, window: Duration, req_count: u64, resp_count: u64, loss_percent: f64, avg_latency: Option<u128>) {
+        for (i, period) in PERIODS.iter().enumerate() {
+            let decay = (-window.as_secs_f64() / period.as_secs_f64()).exp();
+            self.loss_percent[i].add(loss_percent, req_count as f64, decay);
+            match avg_latency {
+                Some(latency) => self.latency[i].add(latency as f64, resp_count as f64, decay),
+                None => self.latency[i].add(0.0, 0.0, decay),
+            }
+        }
+        self.updated = Instant::now();
+    }
+
+    /// not updated for the longest period, e.g. a removed server.
+    pub fn is_stale(&self) -> bool {
+        self.updated.elapsed() > PERIODS[PERIODS.len() - 1]
+    }
+
+    pub fn get(&self) -> UdpEchoRollingStats {
+        let latency = |i: usize| self.latency[i].get().map(|l| l.round() as u128);
+
+        UdpEchoRollingStats {
+            loss_percent_1m: self.loss_percent[0].get(),
+            loss_percent_5m: self.loss_percent[1].get(),
+            loss_percent_15m: self.loss_percent[2].get(),
+            avg_latency_1m: latency(0),
+            avg_latency_5m: latency(1),
+            avg_latency_15m: latency(2),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(10);
+
+    fn add_windows(stats: &mut RollingStats, windows: u64, loss_percent: f64) {
+        for _ in 0..windows {
+            stats.add(WINDOW, 100, 100 - loss_percent as u64, loss_percent, Some(1000));
+        }
+    }
+
+    fn assert_near(expected: f64, value: Option<f64>) {
+        let value = value.expect("average");
+        assert!((expected - value).abs() < 0.01, "expected {} got {}", expected, value);
+    }
+
+    #[test]
+    fn test_constant_loss() {
+        let mut stats = RollingStats::default();
+        assert_eq!(None, stats.get().loss_percent_1m);
+        assert_eq!(None, stats.get().avg_latency_15m);
+
+        add_windows(&mut stats, 1, 10.0);
+        assert_near(10.0, stats.get().loss_percent_1m);
+
+        add_windows(&mut stats, 200, 10.0);
+        let rolling = stats.get();
+        assert_near(10.0, rolling.loss_percent_1m);
+        assert_near(10.0, rolling.loss_percent_5m);
+        assert_near(10.0, rolling.loss_percent_15m);
+        assert_eq!(Some(1000), rolling.avg_latency_1m);
+        assert_eq!(Some(1000), rolling.avg_latency_15m);
+    }
+
+    #[test]
+    fn test_loss_converges() {
+        let mut stats = RollingStats::default();
+        add_windows(&mut stats, 360, 0.0);
+
+        // one minute of loss, the shorter periods follow faster.
+        add_windows(&mut stats, 6, 50.0);
+        let rolling = stats.get();
+        let (loss_1m, loss_5m, loss_15m) = (
+            rolling.loss_percent_1m.unwrap(),
+            rolling.loss_percent_5m.unwrap(),
+            rolling.loss_percent_15m.unwrap(),
+        );
+        assert!(loss_1m > loss_5m && loss_5m > loss_15m && loss_15m > 0.0);
+        assert_near(50.0 * (1.0 - (-1.0f64).exp()), Some(loss_1m));
+
+        add_windows(&mut stats, 1440, 50.0);
+        let rolling = stats.get();
+        assert_near(50.0, rolling.loss_percent_1m);
+        assert_near(50.0, rolling.loss_percent_5m);
+        assert_near(50.0, rolling.loss_percent_15m);
+    }
+
+    #[test]
+    fn test_window_without_requests() {
+        let mut stats = RollingStats::default();
+        add_windows(&mut stats, 30, 20.0);
+        let before = stats.get();
+
+        for _ in 0..30 {
+            stats.add(WINDOW, 0, 0, 0.0, None);
+        }
+
+        let after = stats.get();
+        assert_near(before.loss_percent_1m.unwrap(), after.loss_percent_1m);
+        assert_near(before.loss_percent_5m.unwrap(), after.loss_percent_5m);
+        assert_near(before.loss_percent_15m.unwrap(), after.loss_percent_15m);
+        assert_eq!(before.avg_latency_1m, after.avg_latency_1m);
+    }
+
+    #[test]
+    fn test_is_stale() {
+        let mut stats = RollingStats::default();
+        assert!(!stats.is_stale());
+
+        let longest = PERIODS[PERIODS.len() - 1];
+        match Instant::now().checked_sub(longest + Duration::from_secs(1)) {
+            Some(updated) => stats.updated = updated,
+            // the monotonic clock started less than 15 minutes ago.
+            None => return,
+        }
+        assert!(stats.is_stale());
+
+        add_windows(&mut stats, 1, 0.0);
+        assert!(!stats.is_stale());
+    }
+}
@@ -1,51 +1,328 @@
-use crate::udp_echo::packet::Packet;
-use std::net::SocketAddr;
-use std::net::SocketAddrV4;
+use crate::broadcast::BroadcastEvent;
+use crate::udp_echo::analyzer_event::UdpEchoServerStats;
+use crate::udp_echo::key::PacketKeys;
+use crate::udp_echo::packet::{Packet, PacketType};
+use chrono::Local;
+use mehsh_common::config::ConfigResponderFilter;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant, SystemTime};
 use tokio;
 use tokio::net::UdpSocket;
+use tokio::sync::{broadcast, watch};
+use tokio::time;
+
+/// how often the counters of the server are broadcasted.
+const STATS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// clients that did not send a request for this long are forgotten, their count starts again.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// limits the memory spoofed sources can use, further clients get no count
+/// and further sources are rate limited until the second is over.
+const MAX_CLIENTS: usize = 100_000;
 
 pub struct Server {
     socket: UdpSocket,
     buf: Vec<u8>,
+    /// changes of the config apply without binding the socket again.
+    filter: watch::Receiver<ConfigResponderFilter>,
+    keys: Option<PacketKeys>,
+    stats: UdpEchoServerStats,
+    /// packets per source in the current second, for the rate limit.
+    rate: HashMap<IpAddr, u32>,
+    rate_window: Instant,
+    /// answered requests and the last request per client socket, clients tell forward from return loss with it.
+    clients: HashMap<SocketAddr, (u64, Instant)>,
+    broadcast: Option<broadcast::Sender<BroadcastEvent>>,
+}
+
+enum Verdict {
+    Answer(Packet),
+    Malformed,
+    Unauthenticated,
+    Rejected,
+    RateLimited,
 }
 
 impl Server {
     pub async fn new(host: &str) -> Result<Self, ::anyhow::Error> {
-        let socket: SocketAddrV4 = host.parse()?;
+        let socket: SocketAddr = host.parse()?;
         Ok(Server {
-            socket: UdpSocket::bind(socket).await?,
+            socket: Self::bind(socket)?,
             buf: vec![0; 100],
+            filter: watch::channel(ConfigResponderFilter::default()).1,
+            keys: None,
+            stats: UdpEchoServerStats::new(host.to_string()),
+            rate: HashMap::new(),
+            rate_window: Instant::now(),
+            clients: HashMap::new(),
+            broadcast: None,
         })
     }
 
+    pub fn with_filter(self, filter: watch::Receiver<ConfigResponderFilter>) -> Self {
+        Self { filter, ..self }
+    }
+
+    /// requests must be signed with one of the keys, responses are signed with the key of the request.
+    pub fn with_keys(self, keys: Option<PacketKeys>) -> Self {
+        Self { keys, ..self }
+    }
+
+    /// broadcasts the counters periodically, they are not reported otherwise.
+    pub fn with_stats(self, listener: String, broadcast: broadcast::Sender<BroadcastEvent>) -> Self {
+        Self {
+            stats: UdpEchoServerStats::new(listener),
+            broadcast: Some(broadcast),
+            ..self
+        }
+    }
+
+    /// listens on [::] for ipv4 and ipv6, falls back to 0.0.0.0 if the host has no ipv6 support.
+    pub async fn new_dual_stack(port: u16) -> Result<Self, ::anyhow::Error> {
+        match Self::new(&format!("[::]:{}", port)).await {
+            Ok(s) => Ok(s),
+            Err(e) => {
+                eprintln!("server: could not listen on [::]:{} ({}), falling back to ipv4 only", port, e);
+                Self::new(&format!("0.0.0.0:{}", port)).await
+            }
+        }
+    }
+
+    fn bind(addr: SocketAddr) -> Result<UdpSocket, ::anyhow::Error> {
+        let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+
+        if addr.is_ipv6() && addr.ip().is_unspecified() {
+            // accept ipv4 as well (v4 mapped addresses), independent of net.ipv6.bindv6only.
+            socket.set_only_v6(false)?;
+        }
+
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+
+        Ok(UdpSocket::from_std(socket.into())?)
+    }
+
     pub async fn run(mut self) -> Result<(), ::anyhow::Error> {
+        let mut interval = time::interval(STATS_INTERVAL);
+        interval.tick().await;
+
         loop {
-            match self.run_loop().await {
-                Err(e) => {
-                    eprintln!("server err: {:?}", e)
+            ::tokio::select! {
+                _ = interval.tick() => self.report_stats(),
+                res = self.run_loop() => {
+                    if let Err(e) = res {
+                        eprintln!("server err: {:?}", e)
+                    }
                 }
-                _ => (),
-            };
+            }
+        }
+    }
+
+    fn report_stats(&mut self) {
+        self.clients.retain(|_, (_, last_seen)| last_seen.elapsed() < CLIENT_TIMEOUT);
+
+        let next = UdpEchoServerStats::new(self.stats.listener.clone());
+        let stats = ::std::mem::replace(&mut self.stats, next);
+
+        if let Some(broadcast) = &self.broadcast {
+            if let Err(e) = broadcast.send(BroadcastEvent::UdpEchoServerStats(UdpEchoServerStats {
+                date_time: Local::now(),
+                ..stats
+            })) {
+                eprintln!("warning, issue with broadcasting server stats: {:?}", e)
+            }
         }
     }
 
     async fn run_loop(&mut self) -> Result<(), ::anyhow::Error> {
-        let (size, target): (usize, SocketAddr) = self.socket.recv_from(&mut self.buf).await?;
+        let (size, source): (usize, SocketAddr) = self.socket.recv_from(&mut self.buf).await?;
+        let received = SystemTime::now();
+        self.stats.received += 1;
 
-        let recv_packet = Packet::new_from_raw(&self.buf[0..size]).expect("could not read package");
-        let send_package = Packet::new_resp(recv_packet.get_id()).to_bytes();
+        let packet = match self.verdict(size, source, received) {
+            Verdict::Answer(packet) => packet,
+            Verdict::Malformed => {
+                self.stats.malformed += 1;
+                return Ok(());
+            }
+            Verdict::Unauthenticated => {
+                self.stats.unauthenticated += 1;
+                return Ok(());
+            }
+            Verdict::Rejected => {
+                self.stats.rejected += 1;
+                return Ok(());
+            }
+            Verdict::RateLimited => {
+                self.stats.rate_limited += 1;
+                return Ok(());
+            }
+        };
 
-        let mut send_size = 0;
+        // the response is never larger than the request, one response per request.
+        let packet = packet.with_responder_sent(SystemTime::now());
+        let data = match (&self.keys, packet.get_key_id()) {
+            (Some(keys), Some(key_id)) => {
+                *self.stats.key_ids.entry(key_id).or_insert(0) += 1;
+                keys.sign_with(key_id, packet.to_bytes())
+            }
+            (Some(keys), None) => keys.sign(packet.to_bytes()),
+            (None, _) => packet.to_bytes(),
+        };
+        self.socket.send_to(&data, source).await?;
+        self.stats.answered += 1;
 
-        while send_size < size {
-            match self.socket.send_to(&send_package, target).await {
-                Ok(s) => {
-                    send_size = send_size + s;
+        Ok(())
+    }
+
+    fn verdict(&mut self, size: usize, source: SocketAddr, received: SystemTime) -> Verdict {
+        let rate_limit = {
+            let filter = self.filter.borrow();
+            if let Some(allow) = &filter.allow {
+                if !allow.iter().any(|a| a.matches(&source)) {
+                    return Verdict::Rejected;
                 }
-                Err(e) => return Err(e.into()),
-            };
+            }
+            filter.rate_limit
+        };
+
+        // before the signature is verified, a flood must not cost one hmac per packet.
+        if let Some(rate_limit) = rate_limit {
+            if self.rate_window.elapsed() >= Duration::from_secs(1) {
+                self.rate.clear();
+                self.rate_window = Instant::now();
+            }
+
+            let ip = source.ip().to_canonical();
+            if self.rate.len() >= MAX_CLIENTS && !self.rate.contains_key(&ip) {
+                return Verdict::RateLimited;
+            }
+
+            let count = self.rate.entry(ip).or_insert(0);
+            *count += 1;
+            if *count > rate_limit {
+                return Verdict::RateLimited;
+            }
         }
 
-        Ok(())
+        let (key_id, data) = match &self.keys {
+            None => (None, &self.buf[0..size]),
+            Some(keys) => match keys.verify(&self.buf[0..size]) {
+                Ok((key_id, data)) => (Some(key_id), data),
+                Err(_) => return Verdict::Unauthenticated,
+            },
+        };
+
+        let recv_packet = match Packet::new_from_raw(data) {
+            Ok(p) if p.get_type() == &PacketType::Req => p,
+            // responses are never answered, otherwise two servers could echo each other forever.
+            _ => return Verdict::Malformed,
+        };
+
+        let received_count = match self.clients.len() < MAX_CLIENTS || self.clients.contains_key(&source) {
+            true => {
+                let client = self.clients.entry(source).or_insert((0, Instant::now()));
+                client.0 += 1;
+                client.1 = Instant::now();
+                client.0
+            }
+            false => 0,
+        };
+
+        Verdict::Answer(
+            Packet::new_resp(&recv_packet, received)
+                .with_received_count(received_count)
+                .with_key_id(key_id),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::udp_echo::packet::VERSION_2;
+    use mehsh_common::config::allow_addr::AllowAddr;
+
+    const KEY: &str = "0123456789abcdef";
+
+    async fn server(allow: Option<&str>, rate_limit: Option<u32>) -> Server {
+        let filter = ConfigResponderFilter {
+            allow: allow.map(|a| vec![AllowAddr::new_from_str(a).unwrap()]),
+            rate_limit,
+        };
+        Server::new("127.0.0.1:0")
+            .await
+            .unwrap()
+            .with_filter(watch::channel(filter).1)
+            .with_keys(Some(PacketKeys::parse(KEY).unwrap()))
+    }
+
+    fn verdict(server: &mut Server, data: &[u8], source: &str) -> Verdict {
+        server.buf[..data.len()].copy_from_slice(data);
+        server.verdict(data.len(), source.parse().unwrap(), SystemTime::now())
+    }
+
+    fn signed_req(id: u64) -> Vec<u8> {
+        let keys = PacketKeys::parse(KEY).unwrap();
+        keys.sign(Packet::new_req(id, SystemTime::now(), VERSION_2).to_bytes())
+    }
+
+    #[tokio::test]
+    async fn test_verdict() {
+        let mut server = server(Some("v4:127.0.0.1"), None).await;
+
+        assert!(matches!(verdict(&mut server, &signed_req(1), "127.0.0.2:5000"), Verdict::Rejected));
+        assert!(matches!(verdict(&mut server, &[0; 40], "127.0.0.1:5000"), Verdict::Unauthenticated));
+
+        let mut tampered = signed_req(1);
+        tampered[0] ^= 1;
+        assert!(matches!(verdict(&mut server, &tampered, "127.0.0.1:5000"), Verdict::Unauthenticated));
+
+        // signed, but no request.
+        let keys = PacketKeys::parse(KEY).unwrap();
+        assert!(matches!(verdict(&mut server, &keys.sign(vec![1, 2, 3]), "127.0.0.1:5000"), Verdict::Malformed));
+        let resp = Packet::new_resp(&Packet::new_req(1, SystemTime::now(), VERSION_2), SystemTime::now());
+        assert!(matches!(verdict(&mut server, &keys.sign(resp.to_bytes()), "127.0.0.1:5000"), Verdict::Malformed));
+
+        // every client socket is counted on its own.
+        for (id, source, received_count) in [(1, "127.0.0.1:5000", 1), (2, "127.0.0.1:5000", 2), (1, "127.0.0.1:5001", 1)] {
+            match verdict(&mut server, &signed_req(id), source) {
+                Verdict::Answer(p) => {
+                    assert_eq!(id, p.get_id());
+                    assert_eq!(&PacketType::Resp, p.get_type());
+                    assert_eq!(Some(received_count), p.get_received_count());
+                    assert_eq!(Some(0), p.get_key_id());
+                }
+                _ => panic!("expected an answer"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verdict_rate_limit() {
+        let mut server = server(None, Some(2)).await;
+
+        // packets that fail the verification count as well.
+        assert!(matches!(verdict(&mut server, &[0; 40], "127.0.0.1:5000"), Verdict::Unauthenticated));
+        assert!(matches!(verdict(&mut server, &signed_req(1), "127.0.0.1:5001"), Verdict::Answer(_)));
+        assert!(matches!(verdict(&mut server, &[0; 40], "127.0.0.1:5000"), Verdict::RateLimited));
+        assert!(matches!(verdict(&mut server, &signed_req(2), "127.0.0.1:5001"), Verdict::RateLimited));
+
+        // other sources have their own limit.
+        assert!(matches!(verdict(&mut server, &signed_req(1), "127.0.0.2:5000"), Verdict::Answer(_)));
+
+        // the limit applies per second.
+        server.rate_window -= Duration::from_secs(1);
+        assert!(matches!(verdict(&mut server, &signed_req(3), "127.0.0.1:5001"), Verdict::Answer(_)));
+
+        // once the table is full only known sources are answered.
+        for i in server.rate.len()..MAX_CLIENTS {
+            server.rate.insert(IpAddr::V4((0x0b00_0000 + i as u32).into()), 0);
+        }
+        assert!(matches!(verdict(&mut server, &signed_req(1), "127.0.0.3:5000"), Verdict::RateLimited));
+        assert!(matches!(verdict(&mut server, &signed_req(4), "127.0.0.1:5001"), Verdict::Answer(_)));
     }
 }
@@ -0,0 +1,72 @@
+use crate::resolver::HostResolver;
+use mehsh_common::config::Config;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// validates the config and prints every problem, returns the exit code.
+pub fn run_validate(filename: PathBuf) -> i32 {
+    let config = match Config::parse_file(filename) {
+        Ok(c) => c,
+        Err(e) => {
+            println!("error: {:#}", e);
+            return 1;
+        }
+    };
+
+    let problems = config.validate();
+    let error_count = problems.iter().filter(|p| !p.is_warning()).count();
+
+    for problem in &problems {
+        if problem.is_warning() {
+            println!("warning: {}", problem);
+        } else {
+            println!("error: {}", problem);
+        }
+    }
+
+    for (location, selector, servers) in config.selector_expansions() {
+        let servers = match servers.is_empty() {
+            true => "(no server)".to_string(),
+            false => servers.join(", "),
+        };
+        println!("{}: {} -> {}", location, selector, servers);
+    }
+
+    if error_count > 0 {
+        println!(
+            "config is invalid, {} error(s), {} warning(s)",
+            error_count,
+            problems.len() - error_count
+        );
+        return 1;
+    }
+
+    let mut resolved = HashMap::new();
+    let mut warning_count = problems.len();
+    for hostname in config.hostnames() {
+        match HostResolver::lookup_blocking(&hostname) {
+            Ok(addrs) => {
+                println!("{} resolves to {}", hostname, HostResolver::format_addrs(&addrs));
+                resolved.insert(hostname, addrs);
+            }
+            Err(e) => {
+                println!("warning: could not resolve {}: {}", hostname, e);
+                warning_count += 1;
+            }
+        }
+    }
+
+    let config = config.with_resolved_hostnames(&resolved);
+
+    let check_count = config.all_checks().map(|c| c.len()).unwrap_or(0);
+    let analysis_count = config.all_analyisis().map(|a| a.len()).unwrap_or(0);
+
+    println!(
+        "config is valid, {} warning(s), {} checks, {} analysis",
+        warning_count,
+        check_count,
+        analysis_count
+    );
+
+    0
+}
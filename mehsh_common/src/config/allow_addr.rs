@@ -1,34 +1,66 @@
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use anyhow::anyhow;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum AllowAddrPort {
     Port(usize),
     Range(usize, usize),
     AnyPort,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum AllowAddr {
     V4(Ipv4Addr, AllowAddrPort),
-    V6(String, AllowAddrPort),
+    V6(Ipv6Addr, AllowAddrPort),
     Server(String, AllowAddrPort),
     Group(String, AllowAddrPort),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum AllowIp {
     V4(Ipv4Addr),
-    V6(String),
+    V6(Ipv6Addr),
 }
 
 impl AllowIp {
+    /// accepts a plain ipv4 / ipv6 address or one prefixed with 'v4:' / 'v6:'.
+    pub fn new_from_str(data: &str) -> Result<Self, ::anyhow::Error> {
+        if let Some(v4) = data.strip_prefix("v4:") {
+            return Ok(AllowIp::V4(v4.parse().map_err(|_| anyhow!("could not parse ipv4 address '{}'", data))?));
+        }
+
+        if let Some(v6) = data.strip_prefix("v6:") {
+            return Ok(AllowIp::V6(v6.parse().map_err(|_| anyhow!("could not parse ipv6 address '{}'", data))?));
+        }
+
+        match data.parse::<IpAddr>() {
+            Ok(IpAddr::V4(v)) => Ok(AllowIp::V4(v)),
+            Ok(IpAddr::V6(v)) => Ok(AllowIp::V6(v)),
+            Err(_) => Err(anyhow!("could not parse ip address '{}'", data)),
+        }
+    }
+
     pub fn to_string(&self) -> String {
         match &self {
             AllowIp::V4(v) => v.to_string(),
             AllowIp::V6(v) => v.to_string(),
         }
     }
+
+    pub fn to_ip_addr(&self) -> IpAddr {
+        match &self {
+            AllowIp::V4(v) => IpAddr::V4(*v),
+            AllowIp::V6(v) => IpAddr::V6(*v),
+        }
+    }
+
+    pub fn to_socket_addr(&self, port: u16) -> SocketAddr {
+        SocketAddr::new(self.to_ip_addr(), port)
+    }
+
+    pub fn is_v6(&self) -> bool {
+        matches!(self, AllowIp::V6(_))
+    }
 }
 
 impl AllowAddrPort {
@@ -47,7 +79,7 @@ impl AllowAddrPort {
             return Err(anyhow!("could not decode addr port {}", s));
         }
 
-        let min = {
+        let min: usize = {
             if let Ok(k) = parts[0].parse() {
                 Ok(k)
             } else {
@@ -63,39 +95,106 @@ impl AllowAddrPort {
             }
         }?;
 
-        if parts[0] > parts[1] {
+        if min > max {
             return Err(anyhow!("range mismatch {}", s));
         }
 
         Ok(AllowAddrPort::Range(min, max))
     }
+
+    pub fn matches(&self, port: u16) -> bool {
+        let port = port as usize;
+        match self {
+            AllowAddrPort::Port(p) => *p == port,
+            AllowAddrPort::Range(min, max) => *min <= port && port <= *max,
+            AllowAddrPort::AnyPort => true,
+        }
+    }
 }
 
 impl AllowAddr {
+    /// `v4:ip`, `v4:ip:port`.
     fn parse_v4(data: &str) -> Result<Self, ::anyhow::Error> {
-        let parts: Vec<_> = data
-            .trim_start_matches("v4:")
-            .split(':')
-            .map(|s| s.to_string())
-            .collect();
+        let (ip, port) = match data.split_once(':') {
+            None => (data, AllowAddrPort::AnyPort),
+            Some((ip, port)) => (ip, AllowAddrPort::new_from_str(port)?),
+        };
 
-        if parts.len() == 1 {
-            return Ok(AllowAddr::V4(parts[0].parse()?, AllowAddrPort::AnyPort));
+        Ok(AllowAddr::V4(
+            ip.parse().map_err(|_| anyhow!("could not parse ipv4 address '{}'", ip))?,
+            port,
+        ))
+    }
+
+    /// `v6:ip`, `v6:[ip]`, `v6:[ip]:port`.
+    fn parse_v6(data: &str) -> Result<Self, ::anyhow::Error> {
+        let (ip, port) = match data.strip_prefix('[') {
+            None => (data, AllowAddrPort::AnyPort),
+            Some(bracketed) => match bracketed.split_once(']') {
+                Some((ip, "")) => (ip, AllowAddrPort::AnyPort),
+                Some((ip, port)) => match port.strip_prefix(':') {
+                    Some(port) => (ip, AllowAddrPort::new_from_str(port)?),
+                    None => return Err(anyhow!("expected ':' after ']' in '{}'", data)),
+                },
+                None => return Err(anyhow!("missing ']' in '{}'", data)),
+            },
+        };
+
+        Ok(AllowAddr::V6(
+            ip.parse().map_err(|_| anyhow!("could not parse ipv6 address '{}'", ip))?,
+            port,
+        ))
+    }
+
+    /// `name`, `name:port`.
+    fn parse_name(data: &str) -> Result<(String, AllowAddrPort), ::anyhow::Error> {
+        let (name, port) = match data.split_once(':') {
+            None => (data, AllowAddrPort::AnyPort),
+            Some((name, port)) => (name, AllowAddrPort::new_from_str(port)?),
+        };
+
+        if name.is_empty() {
+            return Err(anyhow!("missing name"));
         }
 
-        if parts.len() == 2 {
-            return Ok(AllowAddr::V4(
-                parts.first().unwrap().parse()?,
-                AllowAddrPort::new_from_str(&parts[1])?,
-            ));
+        Ok((name.to_string(), port))
+    }
+
+    /// servers and groups must be resolved to addresses first, they never match.
+    pub fn matches(&self, addr: &SocketAddr) -> bool {
+        // ipv4 sources of a dual stack socket are v4 mapped ipv6 addresses.
+        match (self, addr.ip().to_canonical()) {
+            (AllowAddr::V4(ip, port), IpAddr::V4(source)) => *ip == source && port.matches(addr.port()),
+            (AllowAddr::V6(ip, port), IpAddr::V6(source)) => *ip == source && port.matches(addr.port()),
+            _ => false,
         }
+    }
 
-        Err(anyhow!("nöpe"))
+    pub fn port(&self) -> &AllowAddrPort {
+        match self {
+            AllowAddr::V4(_, port) | AllowAddr::V6(_, port) | AllowAddr::Server(_, port) | AllowAddr::Group(_, port) => port,
+        }
     }
 
     pub fn new_from_str(data: &str) -> Result<Self, ::anyhow::Error> {
-        if data.starts_with("v4:") {
-            return Self::parse_v4(data);
+        let with_context = |e: ::anyhow::Error| anyhow!("could not parse '{}': {}", data, e);
+
+        if let Some(v4) = data.strip_prefix("v4:") {
+            return Self::parse_v4(v4).map_err(with_context);
+        }
+
+        if let Some(v6) = data.strip_prefix("v6:") {
+            return Self::parse_v6(v6).map_err(with_context);
+        }
+
+        if let Some(server) = data.strip_prefix("server:") {
+            let (name, port) = Self::parse_name(server).map_err(with_context)?;
+            return Ok(AllowAddr::Server(name, port));
+        }
+
+        if let Some(group) = data.strip_prefix("group:") {
+            let (name, port) = Self::parse_name(group).map_err(with_context)?;
+            return Ok(AllowAddr::Group(name, port));
         }
 
         Err(anyhow!("could not parse '{}' the identifier should start with 'v4:', 'v6:', 'server:' or 'group:'", data))
@@ -140,6 +239,88 @@ mod tests {
             ),
             AllowAddr::new_from_str("v4:127.0.0.1:*").unwrap()
         );
+
+        assert_eq!(
+            AllowAddr::V6(Ipv6Addr::from_str("2a01:4f8::1").unwrap(), AllowAddrPort::AnyPort),
+            AllowAddr::new_from_str("v6:2a01:4f8::1").unwrap()
+        );
+
+        assert_eq!(
+            AllowAddr::V6(Ipv6Addr::from_str("::1").unwrap(), AllowAddrPort::AnyPort),
+            AllowAddr::new_from_str("v6:[::1]").unwrap()
+        );
+
+        assert_eq!(
+            AllowAddr::V6(Ipv6Addr::from_str("2a01:4f8::1").unwrap(), AllowAddrPort::Port(2121)),
+            AllowAddr::new_from_str("v6:[2a01:4f8::1]:2121").unwrap()
+        );
+
+        assert_eq!(
+            AllowAddr::V6(Ipv6Addr::from_str("2a01:4f8::1").unwrap(), AllowAddrPort::Range(20, 25)),
+            AllowAddr::new_from_str("v6:[2a01:4f8::1]:20-25").unwrap()
+        );
+
+        assert_eq!(
+            AllowAddr::Server("server1".to_string(), AllowAddrPort::AnyPort),
+            AllowAddr::new_from_str("server:server1").unwrap()
+        );
+
+        assert_eq!(
+            AllowAddr::Group("web".to_string(), AllowAddrPort::Range(4000, 5000)),
+            AllowAddr::new_from_str("group:web:4000-5000").unwrap()
+        );
+
+        assert!(AllowAddr::new_from_str("127.0.0.1").is_err());
+        assert!(AllowAddr::new_from_str("v4:::1").is_err());
+        assert!(AllowAddr::new_from_str("v6:[::1").is_err());
+        assert!(AllowAddr::new_from_str("v6:[::1]2121").is_err());
+        assert!(AllowAddr::new_from_str("v6:[::1]:foo").is_err());
+        assert!(AllowAddr::new_from_str("server:").is_err());
+    }
+
+    #[test]
+    fn test_allow_addr_matches() {
+        let allow = AllowAddr::new_from_str("v4:10.0.0.1:1000-2000").unwrap();
+        assert!(allow.matches(&"10.0.0.1:1500".parse().unwrap()));
+        assert!(allow.matches(&"[::ffff:10.0.0.1]:1500".parse().unwrap()));
+        assert!(!allow.matches(&"10.0.0.1:3000".parse().unwrap()));
+        assert!(!allow.matches(&"10.0.0.2:1500".parse().unwrap()));
+
+        let allow = AllowAddr::new_from_str("v6:[2a01:4f8::1]:1000").unwrap();
+        assert!(allow.matches(&"[2a01:4f8::1]:1000".parse().unwrap()));
+        assert!(!allow.matches(&"[2a01:4f8::1]:1001".parse().unwrap()));
+        assert!(!allow.matches(&"10.0.0.1:1000".parse().unwrap()));
+
+        assert!(!AllowAddr::new_from_str("server:a").unwrap().matches(&"10.0.0.1:1000".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_allow_ip() {
+        assert_eq!(
+            AllowIp::V4(Ipv4Addr::from_str("127.0.0.1").unwrap()),
+            AllowIp::new_from_str("127.0.0.1").unwrap()
+        );
+        assert_eq!(
+            AllowIp::V4(Ipv4Addr::from_str("127.0.0.1").unwrap()),
+            AllowIp::new_from_str("v4:127.0.0.1").unwrap()
+        );
+        assert_eq!(
+            AllowIp::V6(Ipv6Addr::from_str("2a01:4f8::1").unwrap()),
+            AllowIp::new_from_str("2a01:4f8::1").unwrap()
+        );
+        assert_eq!(
+            AllowIp::V6(Ipv6Addr::from_str("::1").unwrap()),
+            AllowIp::new_from_str("v6:::1").unwrap()
+        );
+        assert_eq!(
+            "[2a01:4f8::1]:4232",
+            AllowIp::new_from_str("2a01:4f8::1")
+                .unwrap()
+                .to_socket_addr(4232)
+                .to_string()
+        );
+        assert!(AllowIp::new_from_str("v4:::1").is_err());
+        assert!(AllowIp::new_from_str("foo").is_err());
     }
 
     #[test]
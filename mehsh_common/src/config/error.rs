@@ -0,0 +1,248 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// points to an entry in the config, e.g. `servers.d/db.toml: check[2].to`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigLocation {
+    pub file: Option<PathBuf>,
+    pub section: &'static str,
+    pub index: usize,
+    pub field: Option<String>,
+}
+
+impl ConfigLocation {
+    pub fn new(section: &'static str, index: usize) -> Self {
+        Self {
+            file: None,
+            section,
+            index,
+            field: None,
+        }
+    }
+
+    pub fn with_file(self, file: Option<PathBuf>) -> Self {
+        Self { file, ..self }
+    }
+
+    pub fn field<F: Into<String>>(&self, field: F) -> Self {
+        Self {
+            file: self.file.clone(),
+            section: self.section,
+            index: self.index,
+            field: Some(field.into()),
+        }
+    }
+}
+
+impl fmt::Display for ConfigLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}: ", file.display())?;
+        }
+
+        // `[defaults]` and `[responder]` are tables, not arrays of tables.
+        match self.section {
+            "defaults" | "responder" => write!(f, "{}", self.section)?,
+            _ => write!(f, "{}[{}]", self.section, self.index)?,
+        }
+        if let Some(field) = &self.field {
+            write!(f, ".{}", field)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    Parse {
+        file: Option<PathBuf>,
+        message: String,
+    },
+    Include {
+        file: Option<PathBuf>,
+        pattern: String,
+        message: String,
+    },
+    ServerSelfNotFound {
+        identifier: String,
+    },
+    DuplicateServer {
+        location: ConfigLocation,
+        first_location: Box<ConfigLocation>,
+        identifier: String,
+    },
+    DuplicateGroup {
+        location: ConfigLocation,
+        first_location: Box<ConfigLocation>,
+        name: String,
+    },
+    AmbiguousIdentifier {
+        location: ConfigLocation,
+        identifier: String,
+    },
+    UnknownIdentifier {
+        location: ConfigLocation,
+        identifier: String,
+    },
+    UnknownGroup {
+        location: ConfigLocation,
+        name: String,
+    },
+    InvalidIp {
+        location: ConfigLocation,
+        reason: String,
+    },
+    ReservedInterfaceName {
+        location: ConfigLocation,
+        name: String,
+    },
+    UnknownCheckType {
+        location: ConfigLocation,
+        check: String,
+    },
+    MissingHttpUrl {
+        location: ConfigLocation,
+    },
+    ServerWithoutCheck {
+        location: ConfigLocation,
+        identifier: String,
+    },
+    InvalidValue {
+        location: ConfigLocation,
+        reason: String,
+    },
+    InvalidSelector {
+        location: ConfigLocation,
+        reason: String,
+    },
+    GroupCycle {
+        location: ConfigLocation,
+        cycle: Vec<String>,
+    },
+    MissingInterface {
+        location: Box<ConfigLocation>,
+        server_location: Box<ConfigLocation>,
+        identifier: String,
+        interface: String,
+    },
+}
+
+impl ConfigError {
+    /// warnings are reported by `validate`, but do not prevent mehsh from starting.
+    pub fn is_warning(&self) -> bool {
+        matches!(
+            self,
+            ConfigError::UnknownGroup { .. }
+                | ConfigError::ServerWithoutCheck { .. }
+                | ConfigError::MissingInterface { .. }
+        )
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Parse { file: None, message } => {
+                write!(f, "could not parse config: {}", message)
+            }
+            ConfigError::Parse {
+                file: Some(file),
+                message,
+            } => write!(f, "{}: could not parse config: {}", file.display(), message),
+            ConfigError::Include {
+                file,
+                pattern,
+                message,
+            } => match file {
+                Some(file) => write!(f, "{}: could not include {}: {}", file.display(), pattern, message),
+                None => write!(f, "could not include {}: {}", pattern, message),
+            },
+            ConfigError::ServerSelfNotFound { identifier } => {
+                write!(f, "could not find server {} in config", identifier)
+            }
+            ConfigError::DuplicateServer {
+                location,
+                first_location,
+                identifier,
+            } => write!(
+                f,
+                "{}: server {} already registered at {}",
+                location, identifier, first_location
+            ),
+            ConfigError::DuplicateGroup {
+                location,
+                first_location,
+                name,
+            } => write!(
+                f,
+                "{}: group {} already registered at {}",
+                location, name, first_location
+            ),
+            ConfigError::AmbiguousIdentifier { location, identifier } => write!(
+                f,
+                "{}: {} is ambiguous, there is a server and a group with this name",
+                location, identifier
+            ),
+            ConfigError::UnknownIdentifier { location, identifier } => write!(
+                f,
+                "{}: {} is neither a server nor a group",
+                location, identifier
+            ),
+            ConfigError::UnknownGroup { location, name } => {
+                write!(f, "{}: group {} is not defined", location, name)
+            }
+            ConfigError::InvalidIp { location, reason }
+            | ConfigError::InvalidValue { location, reason }
+            | ConfigError::InvalidSelector { location, reason } => {
+                write!(f, "{}: {}", location, reason)
+            }
+            ConfigError::ReservedInterfaceName { location, name } => write!(
+                f,
+                "{}: interface name {} is reserved for ip / ipv6",
+                location, name
+            ),
+            ConfigError::GroupCycle { location, cycle } => {
+                write!(f, "{}: groups include each other: {}", location, cycle.join(" -> "))
+            }
+            ConfigError::MissingInterface {
+                location,
+                server_location,
+                identifier,
+                interface,
+            } => write!(
+                f,
+                "{}: server {} ({}) has no interface {}, its responder does not listen on it",
+                location, identifier, server_location, interface
+            ),
+            ConfigError::UnknownCheckType { location, check } => {
+                write!(f, "{}: unknown check type {}", location, check)
+            }
+            ConfigError::MissingHttpUrl { location } => {
+                write!(f, "{}: http check needs an http_url", location)
+            }
+            ConfigError::ServerWithoutCheck { location, identifier } => {
+                write!(f, "{}: server {} is not part of any check", location, identifier)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// all errors found while loading a config.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid config, {} error(s)", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  {}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
@@ -0,0 +1,303 @@
+use crate::config::error::{ConfigError, ConfigLocation};
+use crate::config::RawConfig;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// file and position of an entry, entries of included files are counted per file.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConfigSource {
+    pub file: Option<PathBuf>,
+    pub index: usize,
+}
+
+/// sources of all entries of the merged config, in the same order as the entries.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSources {
+    pub server: Vec<ConfigSource>,
+    pub group: Vec<ConfigSource>,
+    pub check: Vec<ConfigSource>,
+    pub analysis: Vec<ConfigSource>,
+    /// file of the first `[defaults]` section.
+    pub defaults: Option<ConfigSource>,
+    /// file of the first `[responder]` section.
+    pub responder: Option<ConfigSource>,
+    /// every file and include directory that was read, used to detect changes.
+    pub paths: Vec<PathBuf>,
+}
+
+impl ConfigSources {
+    pub fn location(&self, section: &'static str, index: usize) -> ConfigLocation {
+        let sources = match section {
+            "server" => &self.server,
+            "group" => &self.group,
+            "check" => &self.check,
+            "analysis" => &self.analysis,
+            "defaults" | "responder" => {
+                let source = if section == "defaults" { &self.defaults } else { &self.responder };
+                let file = source.as_ref().and_then(|d| d.file.clone());
+                return ConfigLocation::new(section, index).with_file(file);
+            }
+            _ => return ConfigLocation::new(section, index),
+        };
+
+        match sources.get(index) {
+            Some(source) => ConfigLocation::new(section, source.index).with_file(source.file.clone()),
+            None => ConfigLocation::new(section, index),
+        }
+    }
+}
+
+/// reads a config file or a directory of config files and merges every `include` into one `RawConfig`.
+#[derive(Default)]
+pub struct RawConfigLoader {
+    raw_config: RawConfig,
+    sources: ConfigSources,
+    loaded: HashSet<PathBuf>,
+}
+
+impl RawConfigLoader {
+    pub fn load_bytes(mut self, content: &[u8]) -> Result<(RawConfig, ConfigSources), ConfigError> {
+        let base = PathBuf::from(".");
+        self.merge(None, &base, content)?;
+        Ok((self.raw_config, self.sources))
+    }
+
+    pub fn load_path(mut self, path: &Path) -> Result<(RawConfig, ConfigSources), ConfigError> {
+        if path.is_dir() {
+            self.sources.paths.push(path.to_path_buf());
+            for file in Self::toml_files_in_dir(path)? {
+                self.load_file(&file)?;
+            }
+        } else {
+            self.load_file(path)?;
+        }
+
+        Ok((self.raw_config, self.sources))
+    }
+
+    fn toml_files_in_dir(path: &Path) -> Result<Vec<PathBuf>, ConfigError> {
+        let entries = fs::read_dir(path).map_err(|e| ConfigError::Parse {
+            file: Some(path.to_path_buf()),
+            message: format!("could not read directory: {}", e),
+        })?;
+
+        let mut files = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_file() && p.extension().map(|e| e == "toml").unwrap_or(false))
+            .collect::<Vec<_>>();
+
+        files.sort();
+
+        Ok(files)
+    }
+
+    fn load_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if !self.loaded.insert(canonical) {
+            // already merged, e.g. matched by two include patterns.
+            return Ok(());
+        }
+
+        let content = fs::read(path).map_err(|e| ConfigError::Parse {
+            file: Some(path.to_path_buf()),
+            message: format!("could not read file: {}", e),
+        })?;
+
+        self.sources.paths.push(path.to_path_buf());
+
+        let base = path.parent().map(|p| p.to_path_buf()).unwrap_or_else(|| PathBuf::from("."));
+        self.merge(Some(path.to_path_buf()), &base, &content)
+    }
+
+    fn merge(&mut self, file: Option<PathBuf>, base: &Path, content: &[u8]) -> Result<(), ConfigError> {
+        let content = String::from_utf8(content.to_vec()).map_err(|_| ConfigError::Parse {
+            file: file.clone(),
+            message: "could not read toml, invalid utf8".to_string(),
+        })?;
+
+        let raw_config = toml::from_str::<RawConfig>(&content).map_err(|e| ConfigError::Parse {
+            file: file.clone(),
+            message: e.to_string(),
+        })?;
+
+        let source = |index| ConfigSource {
+            file: file.clone(),
+            index,
+        };
+
+        self.sources.server.extend((0..raw_config.server.len()).map(&source));
+        self.sources.group.extend((0..raw_config.group.len()).map(&source));
+        self.sources.check.extend((0..raw_config.check.as_ref().map(|c| c.len()).unwrap_or(0)).map(&source));
+        self.sources.analysis.extend((0..raw_config.analysis.as_ref().map(|a| a.len()).unwrap_or(0)).map(&source));
+
+        self.raw_config.server.extend(raw_config.server);
+        self.raw_config.group.extend(raw_config.group);
+        if let Some(check) = raw_config.check {
+            self.raw_config.check.get_or_insert_with(Vec::new).extend(check);
+        }
+        if let Some(analysis) = raw_config.analysis {
+            self.raw_config.analysis.get_or_insert_with(Vec::new).extend(analysis);
+        }
+        if let Some(aggregate_labels) = raw_config.aggregate_labels {
+            let labels = self.raw_config.aggregate_labels.get_or_insert_with(Vec::new);
+            for label in aggregate_labels {
+                if !labels.contains(&label) {
+                    labels.push(label);
+                }
+            }
+        }
+        if let Some(responder) = raw_config.responder {
+            self.sources.responder.get_or_insert_with(|| source(0));
+            self.raw_config.responder.get_or_insert_with(Default::default).merge(responder);
+        }
+        if let Some(defaults) = raw_config.defaults {
+            // the including file wins, includes are merged after it.
+            self.sources.defaults.get_or_insert_with(|| source(0));
+            self.raw_config.defaults.get_or_insert_with(Default::default).merge(defaults);
+        }
+
+        for pattern in raw_config.include.unwrap_or_default() {
+            self.include(&file, base, &pattern)?;
+        }
+
+        Ok(())
+    }
+
+    /// patterns are relative to the including file, a directory includes every toml file in it.
+    fn include(&mut self, file: &Option<PathBuf>, base: &Path, pattern: &str) -> Result<(), ConfigError> {
+        let full_pattern = base.join(pattern);
+
+        let include_error = |message: String| ConfigError::Include {
+            file: file.clone(),
+            pattern: pattern.to_string(),
+            message,
+        };
+
+        if full_pattern.is_dir() {
+            self.sources.paths.push(full_pattern.clone());
+            for f in Self::toml_files_in_dir(&full_pattern)? {
+                self.load_file(&f)?;
+            }
+            return Ok(());
+        }
+
+        // watch the directory as well, so new files are picked up on reload.
+        if let Some(dir) = full_pattern.parent() {
+            self.sources.paths.push(dir.to_path_buf());
+        }
+
+        let mut files = glob::glob(&full_pattern.to_string_lossy())
+            .map_err(|e| include_error(e.to_string()))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| include_error(e.to_string()))?;
+
+        files.sort();
+
+        for f in files.iter().filter(|f| f.is_file()) {
+            self.load_file(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::error::ConfigError;
+    use crate::config::Config;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn write_config_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = ::std::env::temp_dir().join(format!("mehsh_loader_{}_{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (file, content) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = write_config_dir(
+            "include",
+            &[
+                (
+                    "mehsh.toml",
+                    r#"
+include = ["servers.d/*.toml", "checks.d"]
+
+[[group]]
+name = "all"
+"#,
+                ),
+                (
+                    "servers.d/a.toml",
+                    r#"
+[[server]]
+name = "server1"
+ip = "127.0.0.1"
+groups = ["all"]
+"#,
+                ),
+                (
+                    "servers.d/b.toml",
+                    r#"
+[[server]]
+name = "server2"
+ip = "127.0.0.2"
+groups = ["all"]
+
+[[server]]
+name = "server1"
+ip = "127.0.0.3"
+groups = ["all"]
+"#,
+                ),
+                (
+                    "checks.d/all.toml",
+                    r#"
+[[check]]
+from = "all"
+to = "all"
+check = "udp_ping"
+"#,
+                ),
+            ],
+        );
+
+        let config = Config::parse_file(dir.join("mehsh.toml")).expect("could not parse config");
+
+        assert_eq!(4, config.all_checks().unwrap().len());
+        assert_eq!(
+            vec![format!(
+                "{}: server[1].name: server server1 already registered at {}: server[0]",
+                dir.join("servers.d/b.toml").display(),
+                dir.join("servers.d/a.toml").display(),
+            )],
+            config
+                .validate()
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        );
+
+        // a directory is loaded like a file that includes every toml file in it.
+        let config = Config::parse_file(dir.join("servers.d")).expect("could not parse config dir");
+        assert_eq!(
+            3,
+            config
+                .validate()
+                .iter()
+                .filter(|e| matches!(e, ConfigError::ServerWithoutCheck { .. }))
+                .count()
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,13 +1,20 @@
-use crate::config::allow_addr::AllowIp;
+use crate::config::allow_addr::{AllowAddr, AllowIp};
+use crate::config::error::{ConfigError, ConfigErrors, ConfigLocation};
+use crate::config::loader::{ConfigSources, RawConfigLoader};
+use crate::config::selector::Selector;
 use serde::Deserialize;
-use std::collections::hash_map::Entry;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
-use anyhow::Context;
+use std::str::FromStr;
+use std::time::Duration;
+use anyhow::{anyhow, Context};
 
-mod allow_addr;
+pub mod allow_addr;
+pub mod error;
+pub mod loader;
+pub mod selector;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct RawConfigServer {
@@ -15,24 +22,53 @@ pub struct RawConfigServer {
     pub identifier: ServerIdentifier,
     pub datacenter: Option<String>,
     pub ip: String,
+    pub ipv6: Option<String>,
+    pub interfaces: Option<BTreeMap<String, RawConfigInterface>>,
     pub groups: Vec<String>,
     pub serverdensity_udp_agent: Option<bool>,
+    pub labels: Option<BTreeMap<String, String>>,
+    /// deprecated, same as the labels `extra1`, `extra2` and `extra3`.
     pub extra1: Option<String>,
     pub extra2: Option<String>,
     pub extra3: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+/// an interface has a single address or a list of addresses (ipv4 and ipv6).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum RawConfigInterface {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl RawConfigInterface {
+    pub fn to_vec(&self) -> Vec<String> {
+        match self {
+            RawConfigInterface::Single(v) => vec![v.clone()],
+            RawConfigInterface::Multiple(v) => v.clone(),
+        }
+    }
+}
+
+/// check types mehsh knows how to run.
+pub const CHECK_TYPES: [&str; 2] = ["udp_ping", "http"];
+
+/// name of the implicit interface that is built from `ip` and `ipv6`.
+pub const DEFAULT_INTERFACE: &str = "default";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ConfigServer {
     pub identifier: ServerIdentifier,
     pub datacenter: Option<String>,
     pub datacenter_as_entries: Vec<String>,
     pub ip: String,
+    pub ipv6: Option<String>,
+    pub interfaces: BTreeMap<String, Vec<String>>,
     pub groups: Vec<String>,
     pub serverdensity_udp_agent: bool,
-    pub extra1: Option<String>,
-    pub extra2: Option<String>,
-    pub extra3: Option<String>,
+    pub labels: BTreeMap<String, String>,
+    /// set if `ip` is a dns name, `ip` / `ipv6` hold the resolved addresses, see `Config::with_resolved_hostnames`.
+    pub hostname: Option<String>,
 }
 
 impl ConfigServer {
@@ -72,23 +108,158 @@ impl ConfigServer {
             buf
         };
 
+        let mut labels = raw.labels.unwrap_or_default();
+        for (name, value) in [("extra1", raw.extra1), ("extra2", raw.extra2), ("extra3", raw.extra3)] {
+            if let Some(value) = value {
+                labels.entry(name.to_string()).or_insert(value);
+            }
+        }
+
+        let hostname = match AllowIp::new_from_str(&raw.ip) {
+            Err(_) if is_hostname(&raw.ip) => Some(raw.ip.clone()),
+            _ => None,
+        };
+
         Self {
             identifier: raw.identifier,
             datacenter: raw.datacenter,
             datacenter_as_entries,
             ip: raw.ip,
+            ipv6: raw.ipv6,
+            interfaces: raw
+                .interfaces
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(name, interface)| (name, interface.to_vec()))
+                .collect(),
             groups: raw.groups,
             serverdensity_udp_agent: raw.serverdensity_udp_agent.unwrap_or(false),
-            extra1: raw.extra1,
-            extra2: raw.extra2,
-            extra3: raw.extra3,
+            labels,
+            hostname,
+        }
+    }
+
+    /// false for a dns name that was not resolved (yet).
+    pub fn is_resolved(&self) -> bool {
+        self.hostname.is_none() || AllowIp::new_from_str(&self.ip).is_ok()
+    }
+
+    /// all addresses of the server, the primary `ip` first.
+    pub fn ips(&self) -> Result<Vec<AllowIp>, ::anyhow::Error> {
+        let mut buf = vec![AllowIp::new_from_str(&self.ip)?];
+
+        if let Some(ipv6) = &self.ipv6 {
+            let ipv6 = AllowIp::new_from_str(ipv6)?;
+            if !ipv6.is_v6() {
+                return Err(anyhow!("server {}: ipv6 '{}' is not an ipv6 address", self.identifier, ipv6.to_string()));
+            }
+            if buf[0].is_v6() {
+                return Err(anyhow!("server {}: ip is already an ipv6 address, ipv6 must not be set", self.identifier));
+            }
+            buf.push(ipv6);
+        }
+
+        Ok(buf)
+    }
+
+    /// addresses of a named interface, `None` if the server does not have the interface.
+    pub fn interface_ips(&self, interface: &str) -> Result<Option<Vec<AllowIp>>, ::anyhow::Error> {
+        if interface == DEFAULT_INTERFACE {
+            return Ok(Some(self.ips()?));
+        }
+
+        match self.interfaces.get(interface) {
+            None => Ok(None),
+            Some(ips) => Ok(Some(
+                ips.iter()
+                    .map(|ip| AllowIp::new_from_str(ip).with_context(|| format!("server {}, interface {}", self.identifier, interface)))
+                    .collect::<Result<Vec<_>, _>>()?,
+            )),
+        }
+    }
+
+    /// addresses of all interfaces, including the default interface.
+    pub fn all_ips(&self) -> Result<Vec<AllowIp>, ::anyhow::Error> {
+        let mut buf = self.ips()?;
+        for interface in self.interfaces.keys() {
+            if let Some(ips) = self.interface_ips(interface)? {
+                buf.extend(ips);
+            }
+        }
+
+        Ok(buf)
+    }
+}
+
+/// a dns name, the last label must not be numeric to not mistake invalid ipv4 addresses for names.
+pub fn is_hostname(value: &str) -> bool {
+    let labels = value.trim_end_matches('.').split('.').collect::<Vec<_>>();
+
+    value.len() <= 253
+        && labels.iter().all(|l| {
+            !l.is_empty()
+                && l.len() <= 63
+                && !l.starts_with('-')
+                && !l.ends_with('-')
+                && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+        && labels
+            .last()
+            .map(|l| !l.chars().all(|c| c.is_ascii_digit()))
+            .unwrap_or(false)
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum IpVersion {
+    V4,
+    V6,
+    #[default]
+    Both,
+}
+
+impl FromStr for IpVersion {
+    type Err = ::anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "v4" => Ok(IpVersion::V4),
+            "v6" => Ok(IpVersion::V6),
+            "both" => Ok(IpVersion::Both),
+            _ => Err(anyhow!("unknown ip version {}, expected v4, v6 or both", s)),
         }
     }
 }
 
+impl IpVersion {
+    pub fn matches(&self, ip: &AllowIp) -> bool {
+        match self {
+            IpVersion::V4 => !ip.is_v6(),
+            IpVersion::V6 => ip.is_v6(),
+            IpVersion::Both => true,
+        }
+    }
+}
+
+/// the loss `min_loss_percent` is compared with, the loss of a window or a rolling average.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LossAverage {
+    #[default]
+    #[serde(rename = "window")]
+    Window,
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct RawConfigGroup {
     name: String,
+    /// servers of these groups are part of this group as well.
+    include_groups: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -96,8 +267,16 @@ pub struct RawConfigAnalysis {
     name: String,
     from: String,
     to: String,
-    min_loss: u32,
+    /// lost packets per window.
+    min_loss: Option<u32>,
+    /// lost packets per window in percent of the sent packets, e.g. `2.5`.
+    min_loss_percent: Option<f64>,
+    /// compare `min_loss_percent` with the rolling average over 1, 5 or 15 minutes instead of the window.
+    min_loss_percent_average: Option<LossAverage>,
+    /// the loss must be above the thresholds for this long, one window is enough if not set.
+    min_loss_duration_ms: Option<u64>,
     command: String,
+    interface: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -106,11 +285,65 @@ pub struct RawConfigCheck {
     to: String,
     check: String,
     http_url: Option<String>,
+    ip_version: Option<IpVersion>,
+    interface: Option<String>,
+    #[serde(flatten)]
+    probe: RawConfigProbe,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+/// probe settings of a check, unset values are taken from `[defaults]`.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RawConfigProbe {
+    pub interval_ms: Option<u64>,
+    pub port: Option<u16>,
+    pub timeout_ms: Option<u64>,
+    pub window_ms: Option<u64>,
+}
+
+impl RawConfigProbe {
+    /// keeps values that are already set.
+    pub fn merge(&mut self, other: RawConfigProbe) {
+        self.interval_ms = self.interval_ms.or(other.interval_ms);
+        self.port = self.port.or(other.port);
+        self.timeout_ms = self.timeout_ms.or(other.timeout_ms);
+        self.window_ms = self.window_ms.or(other.window_ms);
+    }
+}
+
+/// where the echo server listens, see `Config::listeners`.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RawConfigResponder {
+    /// addresses or interface names of the server, listens on all addresses if not set.
+    pub bind: Option<Vec<String>>,
+    /// ports in addition to the ports of the checks to the server.
+    pub ports: Option<Vec<u16>>,
+    /// sources that get an answer, everyone if not set. e.g. `v4:10.0.0.1:*`.
+    pub allow: Option<Vec<String>>,
+    /// max packets per second per source address.
+    pub rate_limit: Option<u32>,
+}
+
+impl RawConfigResponder {
+    /// keeps values that are already set.
+    pub fn merge(&mut self, other: RawConfigResponder) {
+        self.bind = self.bind.take().or(other.bind);
+        self.ports = self.ports.take().or(other.ports);
+        self.allow = self.allow.take().or(other.allow);
+        self.rate_limit = self.rate_limit.or(other.rate_limit);
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct RawConfig {
+    /// files, glob patterns or directories, relative to the file that includes them.
+    include: Option<Vec<String>>,
+    defaults: Option<RawConfigProbe>,
+    responder: Option<RawConfigResponder>,
+    /// results are aggregated per value of these labels, like per datacenter.
+    aggregate_labels: Option<Vec<String>>,
+    #[serde(default)]
     server: Vec<RawConfigServer>,
+    #[serde(default)]
     group: Vec<RawConfigGroup>,
     check: Option<Vec<RawConfigCheck>>,
     analysis: Option<Vec<RawConfigAnalysis>>,
@@ -118,308 +351,2021 @@ pub struct RawConfig {
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    self_server_identifier: ServerIdentifier,
+    self_server_identifier: Option<ServerIdentifier>,
     servers_by_identifier: HashMap<ServerIdentifier, ConfigServer>,
-    server_self: ConfigServer,
+    server_self: Option<ConfigServer>,
     server: Vec<ConfigServer>,
     group: Vec<RawConfigGroup>,
     check: Option<Vec<RawConfigCheck>>,
     analysis: Option<Vec<RawConfigAnalysis>>,
+    defaults: RawConfigProbe,
+    responder: RawConfigResponder,
+    aggregate_labels: Vec<String>,
+    observer: Option<ConfigObserver>,
+    sources: ConfigSources,
+}
+
+/// mehsh runs outside of the mesh, it is not a server of the config and only probes `to`.
+#[derive(Debug, Clone)]
+pub struct ConfigObserver {
+    pub to: String,
+    pub ip_version: IpVersion,
 }
 
 pub type ServerIdentifier = String;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Ident {
     pub identifier: ServerIdentifier,
+    pub interface: String,
     pub ip: AllowIp,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct ConfigCheck {
     pub from: Ident,
     pub to: Ident,
     pub check: String,
     pub http_url: Option<String>,
+    pub probe: ConfigProbe,
+}
+
+pub const DEFAULT_PORT: u16 = 4232;
+
+/// an address and port the echo server listens on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ConfigListener {
+    /// `None` listens on all ipv4 and ipv6 addresses.
+    pub ip: Option<IpAddr>,
+    pub port: u16,
+}
+
+/// which packets the echo server answers.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ConfigResponderFilter {
+    /// `None` answers every source.
+    pub allow: Option<Vec<AllowAddr>>,
+    pub rate_limit: Option<u32>,
+}
+
+impl fmt::Display for ConfigListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.ip {
+            None => write!(f, "*:{}", self.port),
+            Some(ip) => write!(f, "{}", SocketAddr::new(ip, self.port)),
+        }
+    }
+}
+
+/// how a path is probed and how the results are aggregated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ConfigProbe {
+    /// time between two packets.
+    pub interval: Duration,
+    /// udp port of the echo server on the target.
+    pub port: u16,
+    /// a packet without response after this time is lost.
+    pub timeout: Duration,
+    /// results are reported once per window.
+    pub window: Duration,
+}
+
+impl Default for ConfigProbe {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(25),
+            port: DEFAULT_PORT,
+            timeout: Duration::from_millis(1000),
+            window: Duration::from_millis(5000),
+        }
+    }
+}
+
+impl ConfigProbe {
+    pub fn from_raw_config_probe(raw: &RawConfigProbe, defaults: &RawConfigProbe) -> Self {
+        let default = Self::default();
+        let millis = |v: Option<u64>, d: Option<u64>, fallback: Duration| {
+            v.or(d).map(Duration::from_millis).unwrap_or(fallback)
+        };
+
+        Self {
+            interval: millis(raw.interval_ms, defaults.interval_ms, default.interval),
+            port: raw.port.or(defaults.port).unwrap_or(default.port),
+            timeout: millis(raw.timeout_ms, defaults.timeout_ms, default.timeout),
+            window: millis(raw.window_ms, defaults.window_ms, default.window),
+        }
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct ConfigAnalysis {
     pub name: String,
     pub from: ConfigServer,
     pub to: ConfigServer,
-    pub min_loss: u32,
+    /// all thresholds that are set must be reached.
+    pub min_loss: Option<u32>,
+    /// `min_loss_percent` in hundredths of a percent, 2.5% is 250.
+    pub min_loss_basis_points: Option<u32>,
+    pub min_loss_percent_average: LossAverage,
+    pub min_loss_duration: Duration,
     pub command: String,
+    /// only loss on this interface triggers the analysis, `None` means any interface.
+    pub interface: Option<String>,
 }
 
 impl Config {
-    pub fn new_from_bytes(
-        self_server_identifier: ServerIdentifier,
-        content: &[u8],
-    ) -> Result<Self, ::anyhow::Error> {
-        let raw_config = toml::from_str::<RawConfig>(
-            String::from_utf8(content.to_vec()).context("could not read toml, invalid utf8")?.as_str()
-        )?;
+    /// parses the config without selecting the server mehsh runs on.
+    /// the config is not validated, see `validate`.
+    pub fn parse(content: &[u8]) -> Result<Self, ConfigError> {
+        let (raw_config, sources) = RawConfigLoader::default().load_bytes(content)?;
+        Ok(Self::from_raw_config(raw_config, sources))
+    }
 
+    /// parses a config file, or every toml file of a directory, including all files referenced by `include`.
+    pub fn parse_file(filename: PathBuf) -> Result<Self, ConfigError> {
+        let (raw_config, sources) = RawConfigLoader::default().load_path(&filename)?;
+        Ok(Self::from_raw_config(raw_config, sources))
+    }
+
+    fn from_raw_config(raw_config: RawConfig, sources: ConfigSources) -> Self {
         let servers = raw_config
             .server
             .iter()
             .map(|s| ConfigServer::from_raw_config_server(s.clone()))
+            .map(|s| Self::with_included_groups(s, &raw_config.group))
             .collect::<Vec<_>>();
 
         let servers_by_identifiers = {
             let mut map = HashMap::new();
             for s in servers.iter() {
-                map.insert(s.identifier.clone(), s.clone());
+                // duplicate servers are reported by validate.
+                map.entry(s.identifier.clone()).or_insert_with(|| s.clone());
             }
 
             map
         };
 
-        let server_self = servers
-            .iter()
-            .find(|v| v.identifier == self_server_identifier)
-            .map(|v| v.clone())
-            .expect(&format!(
-                "could not find server {} in config",
-                self_server_identifier.as_str()
-            ));
-
-        Ok(Config {
-            self_server_identifier,
+        Config {
+            self_server_identifier: None,
             servers_by_identifier: servers_by_identifiers,
-            server_self,
+            server_self: None,
             server: servers,
             check: raw_config.check,
             group: raw_config.group,
             analysis: raw_config.analysis,
-        })
+            defaults: raw_config.defaults.unwrap_or_default(),
+            responder: raw_config.responder.unwrap_or_default(),
+            aggregate_labels: raw_config.aggregate_labels.unwrap_or_default(),
+            observer: None,
+            sources,
+        }
     }
 
-    pub fn all_analyisis(&self) -> Result<Vec<ConfigAnalysis>, ::anyhow::Error> {
-        let mut buf = HashMap::new();
-        match &self.analysis {
-            None => {}
-            Some(analysis) => {
-                for analysis_entry in analysis {
-                    for from in &self.resolve_idents(analysis_entry.from.clone())? {
-                        for to in &self.resolve_idents(analysis_entry.to.clone())? {
-                            let key = (
-                                from.identifier.clone(),
-                                to.identifier.clone(),
-                                analysis_entry.name.clone(),
-                            );
-                            if buf.contains_key(&key) {
-                                eprintln!("warning, you defined the same analysis multiple times. from: {}, to: {}, analysis: {}", from.identifier.clone(), to.identifier.clone(), analysis_entry.name);
-                                continue;
-                            }
-                            
-                            buf.insert(
-                                key,
-                                ConfigAnalysis {
-                                    from: self.get_server_by_identifier(&from.identifier).expect("invalid server in analysis from, should never happen.").clone(),
-                                    to: self.get_server_by_identifier(&to.identifier).expect("invalid server in analysis to, should never happen.").clone(),
-                                    name: analysis_entry.name.clone(),
-                                    command: analysis_entry.command.clone(),
-                                    min_loss: analysis_entry.min_loss.clone(),
-                                },
-                            );
-                        }
-                    }
+    /// adds every group that includes one of the groups of the server, directly or through other groups.
+    fn with_included_groups(mut server: ConfigServer, groups: &[RawConfigGroup]) -> ConfigServer {
+        let mut pending = server.groups.clone();
+        while let Some(group) = pending.pop() {
+            for parent in groups {
+                if server.groups.contains(&parent.name) {
+                    continue;
+                }
+
+                if parent.include_groups.iter().flatten().any(|g| g == &group) {
+                    server.groups.push(parent.name.clone());
+                    pending.push(parent.name.clone());
                 }
             }
-        };
+        }
 
-        Ok(buf.into_iter().map(|(_k, v)| v).collect::<Vec<_>>())
+        server
     }
 
-    pub fn all_checks(&self) -> Result<Vec<ConfigCheck>, ::anyhow::Error> {
-        let mut buf = HashMap::new();
-        match &self.check {
-            None => {}
-            Some(checks) => {
-                for check in checks {
-                    for from in &self.resolve_idents(check.from.clone())? {
-                        for to in &self.resolve_idents(check.to.clone())? {
-                            let key = (
-                                from.identifier.clone(),
-                                to.identifier.clone(),
-                                check.check.clone(),
-                                check.http_url.clone(),
-                            );
-                            if buf.contains_key(&key) {
-                                eprintln!("warning, you defined the same check multiple times. from: {}, to: {}, check: {}", from.identifier.clone(), to.identifier.clone(), check.check.clone());
-                            }
+    /// dns names used as `ip` of a server.
+    pub fn hostnames(&self) -> BTreeSet<String> {
+        self.server.iter().filter_map(|s| s.hostname.clone()).collect()
+    }
 
-                            buf.insert(
-                                key,
-                                ConfigCheck {
-                                    from: from.clone(),
-                                    to: to.clone(),
-                                    check: check.check.clone(),
-                                    http_url: check.http_url.clone(),
-                                },
-                            );
-                        }
+    /// replaces dns names with their addresses, the first ipv4 address is the `ip`, the first ipv6 address the `ipv6`.
+    /// a configured `ipv6` is kept, servers without addresses stay unresolved and are not part of any check.
+    pub fn with_resolved_hostnames(&self, resolved: &HashMap<String, Vec<IpAddr>>) -> Self {
+        let resolve = |server: &ConfigServer| {
+            let mut server = server.clone();
+            let hostname = match &server.hostname {
+                Some(h) => h,
+                None => return server,
+            };
+
+            let addrs = resolved.get(hostname).map(|a| a.as_slice()).unwrap_or_default();
+            let v4 = addrs.iter().find(|a| a.is_ipv4());
+            let v6 = addrs.iter().find(|a| a.is_ipv6());
+
+            match (v4, v6) {
+                (Some(v4), v6) => {
+                    server.ip = v4.to_string();
+                    if server.ipv6.is_none() {
+                        server.ipv6 = v6.map(|v6| v6.to_string());
                     }
                 }
-            }
+                (None, Some(v6)) => server.ip = v6.to_string(),
+                (None, None) => server.ip = hostname.clone(),
+            };
+
+            server
         };
 
-        Ok(buf.into_iter().map(|(_k, v)| v).collect::<Vec<_>>())
+        let mut config = self.clone();
+        config.server = self.server.iter().map(resolve).collect();
+        config.servers_by_identifier = self.servers_by_identifier.iter().map(|(k, s)| (k.clone(), resolve(s))).collect();
+        config.server_self = self.server_self.as_ref().map(resolve);
+
+        config
+    }
+
+    pub fn new_from_bytes(
+        self_server_identifier: ServerIdentifier,
+        content: &[u8],
+    ) -> Result<Self, ::anyhow::Error> {
+        Self::parse(content)?.with_server_self(self_server_identifier)
     }
 
     pub fn new_from_file(
         self_server_identifier: ServerIdentifier,
         filename: PathBuf,
     ) -> Result<Self, ::anyhow::Error> {
-        let mut content = Vec::new();
-        File::open(filename)?.read_to_end(&mut content)?;
-
-        Self::new_from_bytes(self_server_identifier, &content)
+        Self::parse_file(filename)?.with_server_self(self_server_identifier)
     }
 
-    pub fn is_server_or_is_in_group(&self, server_or_group_identifier: &str) -> bool {
-        self.server_self.identifier == server_or_group_identifier
-            || self
-                .server_self
-                .groups
-                .contains(&server_or_group_identifier.to_string())
+    /// loads the config for an observer, `name` must not be a server of the config.
+    pub fn new_observer_from_file(
+        name: ServerIdentifier,
+        observer: ConfigObserver,
+        filename: PathBuf,
+    ) -> Result<Self, ::anyhow::Error> {
+        let mut config = Self::parse_file(filename)?;
+        config.check_errors()?;
+
+        if config.get_server_by_identifier(&name).is_some() {
+            return Err(anyhow!("observer {} is a server of the config, run it without --observer", name));
+        }
+
+        Selector::parse(&observer.to).context("--to")?;
+
+        config.self_server_identifier = Some(name);
+        config.observer = Some(observer);
+
+        Ok(config)
     }
 
-    pub fn get_server_by_identifier(&self, identifier: &ServerIdentifier) -> Option<&ConfigServer> {
-        self.servers_by_identifier.get(identifier)
+    fn check_errors(&self) -> Result<(), ::anyhow::Error> {
+        let errors = self
+            .validate()
+            .into_iter()
+            .filter(|e| !e.is_warning())
+            .collect::<Vec<_>>();
+
+        if !errors.is_empty() {
+            return Err(ConfigErrors(errors).into());
+        }
+
+        Ok(())
     }
 
-    pub fn get_server_self(&self) -> &ConfigServer {
-        &self.server_self
+    /// validates the config and selects the server mehsh runs on.
+    fn with_server_self(self, self_server_identifier: ServerIdentifier) -> Result<Self, ::anyhow::Error> {
+        let mut config = self;
+        config.check_errors()?;
+
+        let server_self = config
+            .get_server_by_identifier(&self_server_identifier)
+            .cloned()
+            .ok_or_else(|| ConfigError::ServerSelfNotFound {
+                identifier: self_server_identifier.clone(),
+            })?;
+
+        config.server_self = Some(server_self);
+        config.self_server_identifier = Some(self_server_identifier);
+
+        Ok(config)
     }
 
-    pub fn resolve_idents<I>(&self, raw_identifier: I) -> Result<Vec<Ident>, ::anyhow::Error>
-    where
-        I: AsRef<str> + Sized,
-    {
-        let identifier: &str = raw_identifier.as_ref();
-        let raw_servers = {
-            let mut m = HashMap::new();
-            for server in self.server.iter() {
-                match m.entry(server.identifier.clone()) {
-                    Entry::Occupied(v) => {
-                        panic!("server {:?} already registered", v.get());
-                    }
-                    Entry::Vacant(v) => {
-                        v.insert(server.clone());
-                    }
+    /// reports every problem of the config, including warnings.
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = vec![];
+
+        let mut groups = HashMap::new();
+        for (i, group) in self.group.iter().enumerate() {
+            if let Some(first) = groups.insert(group.name.as_str(), i) {
+                groups.insert(group.name.as_str(), first);
+                errors.push(ConfigError::DuplicateGroup {
+                    location: self.sources.location("group", i).field("name"),
+                    first_location: Box::new(self.sources.location("group", first)),
+                    name: group.name.clone(),
+                });
+            }
+        }
+
+        for (i, group) in self.group.iter().enumerate() {
+            for included in group.include_groups.iter().flatten() {
+                if !groups.contains_key(included.as_str()) {
+                    errors.push(ConfigError::UnknownGroup {
+                        location: self.sources.location("group", i).field("include_groups"),
+                        name: included.clone(),
+                    });
                 }
             }
-            m
-        };
+        }
 
-        let raw_groups = {
-            let mut m = HashMap::new();
-            for group in self.group.iter() {
-                match m.entry(group.name.clone()) {
-                    Entry::Occupied(v) => {
-                        panic!("group {:?} already registered", v.get());
-                    }
-                    Entry::Vacant(v) => {
-                        v.insert(group.clone());
-                    }
+        errors.extend(self.validate_group_cycles());
+
+        let mut servers = HashMap::new();
+        for (i, server) in self.server.iter().enumerate() {
+            let location = self.sources.location("server", i);
+
+            if let Some(first) = servers.insert(server.identifier.as_str(), i) {
+                servers.insert(server.identifier.as_str(), first);
+                errors.push(ConfigError::DuplicateServer {
+                    location: location.field("name"),
+                    first_location: Box::new(self.sources.location("server", first)),
+                    identifier: server.identifier.clone(),
+                });
+            }
+
+            if groups.contains_key(server.identifier.as_str()) {
+                errors.push(ConfigError::AmbiguousIdentifier {
+                    location: location.field("name"),
+                    identifier: server.identifier.clone(),
+                });
+            }
+
+            errors.extend(Self::validate_server_ips(server, &location));
+
+            for name in server.labels.keys() {
+                if !Self::is_valid_label_name(name) {
+                    errors.push(ConfigError::InvalidValue {
+                        location: location.field(format!("labels.{}", name)),
+                        reason: format!("label name {} may only contain a-z, A-Z, 0-9, _ and -", name),
+                    });
                 }
             }
-            m
-        };
 
-        if raw_servers.contains_key(identifier) && raw_groups.contains_key(identifier) {
-            panic!("allow {:?} is ambigious (server, groups)", identifier);
+            for group in &server.groups {
+                if !groups.contains_key(group.as_str()) {
+                    errors.push(ConfigError::UnknownGroup {
+                        location: location.field("groups"),
+                        name: group.clone(),
+                    });
+                }
+            }
         }
 
-        if let Some(s) = raw_servers.get(identifier) {
-            return Ok(vec![Ident {
-                identifier: s.identifier.clone(),
-                ip: AllowIp::V4(s.ip.parse()?),
-            }]);
-        }
+        let is_known_identifier =
+            |identifier: &str| servers.contains_key(identifier) || groups.contains_key(identifier);
 
-        if let Some(_) = raw_groups.get(identifier) {
-            let mut buf = vec![];
+        let mut servers_in_checks = HashSet::new();
+        for (i, check) in self.check.iter().flatten().enumerate() {
+            let location = self.sources.location("check", i);
 
-            let servers_in_group = raw_servers
-                .iter()
-                .filter(|(_, s)| s.groups.contains(&identifier.to_string()))
-                .collect::<Vec<_>>();
+            for (field, selector) in [("from", &check.from), ("to", &check.to)] {
+                errors.extend(Self::validate_selector(selector, &location.field(field), &is_known_identifier));
+
+                for server in self.select_servers(selector).unwrap_or_default() {
+                    servers_in_checks.insert(server.identifier.clone());
+                }
+            }
+
+            if !CHECK_TYPES.contains(&check.check.as_str()) {
+                errors.push(ConfigError::UnknownCheckType {
+                    location: location.field("check"),
+                    check: check.check.clone(),
+                });
+            }
 
-            for (_, s) in servers_in_group.iter() {
-                buf.push(Ident {
-                    identifier: s.identifier.clone(),
-                    ip: AllowIp::V4(s.ip.parse()?),
+            if check.check == "http" && check.http_url.is_none() {
+                errors.push(ConfigError::MissingHttpUrl {
+                    location: location.field("http_url"),
                 });
             }
 
-            return Ok(buf);
+            errors.extend(Self::validate_probe(&check.probe, &location));
         }
 
-        Ok(vec![])
-    }
-}
+        errors.extend(Self::validate_probe(&self.defaults, &self.sources.location("defaults", 0)));
+        errors.extend(self.validate_responder());
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        for (i, analysis) in self.analysis.iter().flatten().enumerate() {
+            let location = self.sources.location("analysis", i);
 
-    fn load_config(content: &[u8]) -> Config {
-        Config::new_from_bytes("server1".to_string(), content).expect("could not load config")
-    }
+            for (field, selector) in [("from", &analysis.from), ("to", &analysis.to)] {
+                errors.extend(Self::validate_selector(selector, &location.field(field), &is_known_identifier));
+            }
 
-    #[test]
-    fn test_basic_servers() {
-        let c = load_config(
-            r#"
-[[group]]
-name = "g1"
+            if analysis.min_loss.is_none() && analysis.min_loss_percent.is_none() {
+                errors.push(ConfigError::InvalidValue {
+                    location: location.field("min_loss"),
+                    reason: "analysis needs min_loss or min_loss_percent".to_string(),
+                });
+            }
 
-[[group]]
-name = "g2"
+            if let Some(percent) = analysis.min_loss_percent {
+                if !(percent > 0.0 && percent <= 100.0) {
+                    errors.push(ConfigError::InvalidValue {
+                        location: location.field("min_loss_percent"),
+                        reason: format!("min_loss_percent must be between 0 and 100, got {}", percent),
+                    });
+                }
+            }
+        }
 
-[[server]]
-name = "server1"
-ip = "127.0.0.1"
-datacenter = "fra.dc11.foo.xyz"
-groups = ["g1"]
+        for (i, server) in self.server.iter().enumerate() {
+            if !servers_in_checks.contains(&server.identifier) {
+                errors.push(ConfigError::ServerWithoutCheck {
+                    location: self.sources.location("server", i).field("name"),
+                    identifier: server.identifier.clone(),
+                });
+            }
+        }
 
-[[server]]
-name = "server2"
+        errors
+    }
+
+    fn validate_responder(&self) -> Vec<ConfigError> {
+        let mut errors = vec![];
+        let location = self.sources.location("responder", 0);
+
+        for entry in self.responder.bind.iter().flatten() {
+            if AllowIp::new_from_str(entry).is_ok() || entry == DEFAULT_INTERFACE {
+                continue;
+            }
+
+            for (i, server) in self.server.iter().enumerate() {
+                if !server.interfaces.contains_key(entry) {
+                    errors.push(ConfigError::MissingInterface {
+                        location: Box::new(location.field("bind")),
+                        server_location: Box::new(self.sources.location("server", i)),
+                        identifier: server.identifier.clone(),
+                        interface: entry.clone(),
+                    });
+                }
+            }
+        }
+
+        for allow in self.responder.allow.iter().flatten() {
+            match AllowAddr::new_from_str(allow) {
+                Err(e) => errors.push(ConfigError::InvalidValue {
+                    location: location.field("allow"),
+                    reason: e.to_string(),
+                }),
+                Ok(AllowAddr::Server(identifier, _)) if !self.servers_by_identifier.contains_key(&identifier) => {
+                    errors.push(ConfigError::UnknownIdentifier {
+                        location: location.field("allow"),
+                        identifier,
+                    })
+                }
+                Ok(AllowAddr::Group(name, _)) if !self.group.iter().any(|g| g.name == name) => {
+                    errors.push(ConfigError::UnknownGroup {
+                        location: location.field("allow"),
+                        name,
+                    })
+                }
+                Ok(_) => {}
+            }
+        }
+
+        if self.responder.rate_limit == Some(0) {
+            errors.push(ConfigError::InvalidValue {
+                location: location.field("rate_limit"),
+                reason: "rate_limit must be greater than 0".to_string(),
+            });
+        }
+
+        if self.responder.ports.iter().flatten().any(|p| *p == 0) {
+            errors.push(ConfigError::InvalidValue {
+                location: location.field("ports"),
+                reason: "ports must be greater than 0".to_string(),
+            });
+        }
+
+        errors
+    }
+
+    fn validate_group_cycles(&self) -> Vec<ConfigError> {
+        let mut errors = vec![];
+        let mut done = HashSet::new();
+
+        for (i, group) in self.group.iter().enumerate() {
+            let mut path = vec![group.name.as_str()];
+            self.find_group_cycles(i, &mut path, &mut done, &mut errors);
+        }
+
+        errors
+    }
+
+    /// depth first search, `path` ends with the group at `index`.
+    fn find_group_cycles<'a>(
+        &'a self,
+        index: usize,
+        path: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+        errors: &mut Vec<ConfigError>,
+    ) {
+        let group = &self.group[index];
+        if done.contains(group.name.as_str()) {
+            return;
+        }
+
+        for included in group.include_groups.iter().flatten() {
+            if let Some(start) = path.iter().position(|g| *g == included.as_str()) {
+                let mut cycle = path[start..].iter().map(|g| g.to_string()).collect::<Vec<_>>();
+                cycle.push(included.clone());
+                errors.push(ConfigError::GroupCycle {
+                    location: self.sources.location("group", index).field("include_groups"),
+                    cycle,
+                });
+                continue;
+            }
+
+            // the first definition wins, duplicate groups are reported as well.
+            if let Some(next) = self.group.iter().position(|g| &g.name == included) {
+                path.push(included.as_str());
+                self.find_group_cycles(next, path, done, errors);
+                path.pop();
+            }
+        }
+
+        done.insert(group.name.as_str());
+    }
+
+    fn validate_selector(
+        selector: &str,
+        location: &ConfigLocation,
+        is_known_identifier: &dyn Fn(&str) -> bool,
+    ) -> Vec<ConfigError> {
+        let selector = match Selector::parse(selector) {
+            Ok(s) => s,
+            Err(e) => {
+                return vec![ConfigError::InvalidSelector {
+                    location: location.clone(),
+                    reason: format!("{:#}", e),
+                }]
+            }
+        };
+
+        selector
+            .names()
+            .into_iter()
+            .filter(|name| !is_known_identifier(name))
+            .map(|name| ConfigError::UnknownIdentifier {
+                location: location.clone(),
+                identifier: name.to_string(),
+            })
+            .collect()
+    }
+
+    /// every `from` and `to` of checks and analysis with the servers it selects.
+    pub fn selector_expansions(&self) -> Vec<(ConfigLocation, String, Vec<ServerIdentifier>)> {
+        let mut buf = vec![];
+
+        let sections = [
+            ("check", self.check.iter().flatten().map(|c| (&c.from, &c.to)).collect::<Vec<_>>()),
+            ("analysis", self.analysis.iter().flatten().map(|a| (&a.from, &a.to)).collect::<Vec<_>>()),
+        ];
+
+        for (section, entries) in sections {
+            for (i, (from, to)) in entries.into_iter().enumerate() {
+                let location = self.sources.location(section, i);
+                for (field, selector) in [("from", from), ("to", to)] {
+                    let servers = self
+                        .select_servers(selector)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|s| s.identifier.clone())
+                        .collect();
+                    buf.push((location.field(field), selector.clone(), servers));
+                }
+            }
+        }
+
+        buf
+    }
+
+    fn is_valid_label_name(name: &str) -> bool {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    }
+
+    fn validate_probe(probe: &RawConfigProbe, location: &ConfigLocation) -> Vec<ConfigError> {
+        let mut errors = vec![];
+
+        for (field, value) in [
+            ("interval_ms", probe.interval_ms),
+            ("port", probe.port.map(u64::from)),
+            ("timeout_ms", probe.timeout_ms),
+            ("window_ms", probe.window_ms),
+        ] {
+            if value == Some(0) {
+                errors.push(ConfigError::InvalidValue {
+                    location: location.field(field),
+                    reason: format!("{} must be greater than 0", field),
+                });
+            }
+        }
+
+        errors
+    }
+
+    fn validate_server_ips(server: &ConfigServer, location: &ConfigLocation) -> Vec<ConfigError> {
+        let mut errors = vec![];
+
+        let ip = match AllowIp::new_from_str(&server.ip) {
+            Ok(ip) => Some(ip),
+            Err(_) if server.hostname.is_some() => None,
+            Err(e) => {
+                errors.push(ConfigError::InvalidIp {
+                    location: location.field("ip"),
+                    reason: e.to_string(),
+                });
+                None
+            }
+        };
+
+        if let Some(ipv6) = &server.ipv6 {
+            match AllowIp::new_from_str(ipv6) {
+                Err(e) => errors.push(ConfigError::InvalidIp {
+                    location: location.field("ipv6"),
+                    reason: e.to_string(),
+                }),
+                Ok(v) if !v.is_v6() => errors.push(ConfigError::InvalidIp {
+                    location: location.field("ipv6"),
+                    reason: format!("'{}' is not an ipv6 address", ipv6),
+                }),
+                Ok(_) if ip.as_ref().map(|ip| ip.is_v6()).unwrap_or(false) => {
+                    errors.push(ConfigError::InvalidIp {
+                        location: location.field("ipv6"),
+                        reason: "ip is already an ipv6 address, ipv6 must not be set".to_string(),
+                    })
+                }
+                Ok(_) => {}
+            }
+        }
+
+        for (name, ips) in &server.interfaces {
+            if name == DEFAULT_INTERFACE {
+                errors.push(ConfigError::ReservedInterfaceName {
+                    location: location.field(format!("interfaces.{}", name)),
+                    name: name.clone(),
+                });
+            }
+
+            for ip in ips {
+                if let Err(e) = AllowIp::new_from_str(ip) {
+                    errors.push(ConfigError::InvalidIp {
+                        location: location.field(format!("interfaces.{}", name)),
+                        reason: e.to_string(),
+                    });
+                }
+            }
+        }
+
+        errors
+    }
+
+    pub fn all_analyisis(&self) -> Result<Vec<ConfigAnalysis>, ::anyhow::Error> {
+        let mut buf = HashMap::new();
+        match &self.analysis {
+            None => {}
+            Some(analysis) => {
+                for analysis_entry in analysis {
+                    for from in &self.resolve_idents(analysis_entry.from.clone())? {
+                        for to in &self.resolve_idents(analysis_entry.to.clone())? {
+                            let key = (
+                                from.identifier.clone(),
+                                to.identifier.clone(),
+                                analysis_entry.name.clone(),
+                            );
+                            if buf.contains_key(&key) {
+                                eprintln!("warning, you defined the same analysis multiple times. from: {}, to: {}, analysis: {}", from.identifier.clone(), to.identifier.clone(), analysis_entry.name);
+                                continue;
+                            }
+                            
+                            buf.insert(
+                                key,
+                                ConfigAnalysis {
+                                    from: self.get_server_by_identifier(&from.identifier).expect("invalid server in analysis from, should never happen.").clone(),
+                                    to: self.get_server_by_identifier(&to.identifier).expect("invalid server in analysis to, should never happen.").clone(),
+                                    name: analysis_entry.name.clone(),
+                                    command: analysis_entry.command.clone(),
+                                    min_loss: analysis_entry.min_loss,
+                                    min_loss_basis_points: analysis_entry
+                                        .min_loss_percent
+                                        .map(|p| (p * 100.0).round() as u32),
+                                    min_loss_percent_average: analysis_entry
+                                        .min_loss_percent_average
+                                        .unwrap_or_default(),
+                                    min_loss_duration: Duration::from_millis(
+                                        analysis_entry.min_loss_duration_ms.unwrap_or(0),
+                                    ),
+                                    interface: analysis_entry.interface.clone(),
+                                },
+                            );
+                        }
+                    }
+                }
+            }
+        };
+
+        Ok(buf.into_iter().map(|(_k, v)| v).collect::<Vec<_>>())
+    }
+
+    pub fn all_checks(&self) -> Result<Vec<ConfigCheck>, ::anyhow::Error> {
+        let mut buf = HashMap::new();
+        match &self.check {
+            None => {}
+            Some(checks) => {
+                for check in checks {
+                    let ip_version = check.ip_version.unwrap_or_default();
+                    let interface = check.interface.clone().unwrap_or(DEFAULT_INTERFACE.to_string());
+                    let probe = ConfigProbe::from_raw_config_probe(&check.probe, &self.defaults);
+                    for from in &self.resolve_idents(check.from.clone())? {
+                        for to in &self.resolve_idents(check.to.clone())? {
+                            for to_ip in self.check_target_ips(check, ip_version, &interface, from, to)? {
+                                // checks to other ports of the same server are separate checks.
+                                let key = (
+                                    from.identifier.clone(),
+                                    to.identifier.clone(),
+                                    to_ip.clone(),
+                                    check.check.clone(),
+                                    check.http_url.clone(),
+                                    probe,
+                                );
+                                if buf.contains_key(&key) {
+                                    eprintln!("warning, you defined the same check multiple times. from: {}, to: {}, check: {}", from.identifier.clone(), to.identifier.clone(), check.check.clone());
+                                }
+
+                                buf.insert(
+                                    key,
+                                    ConfigCheck {
+                                        from: from.clone(),
+                                        to: Ident {
+                                            identifier: to.identifier.clone(),
+                                            interface: interface.clone(),
+                                            ip: to_ip,
+                                        },
+                                        check: check.check.clone(),
+                                        http_url: check.http_url.clone(),
+                                        probe,
+                                    },
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        };
+
+        Ok(buf.into_iter().map(|(_k, v)| v).collect::<Vec<_>>())
+    }
+
+    /// checks mehsh runs, the checks from this server or the checks of the observer.
+    pub fn self_checks(&self) -> Result<Vec<ConfigCheck>, ::anyhow::Error> {
+        let observer = match &self.observer {
+            Some(o) => o,
+            None => {
+                return Ok(self
+                    .all_checks()?
+                    .into_iter()
+                    .filter(|c| c.from.identifier == self.get_self_identifier())
+                    .collect())
+            }
+        };
+
+        let from = Ident {
+            identifier: self.get_self_identifier().to_string(),
+            interface: DEFAULT_INTERFACE.to_string(),
+            // the observer has no address in the config, the client binds to any address.
+            ip: AllowIp::V4(Ipv4Addr::UNSPECIFIED),
+        };
+        let probe = ConfigProbe::from_raw_config_probe(&RawConfigProbe::default(), &self.defaults);
+
+        let mut buf = vec![];
+        for to in self.resolve_idents(&observer.to)? {
+            let server = &self.servers_by_identifier[&to.identifier];
+            for ip in server.ips()?.into_iter().filter(|ip| observer.ip_version.matches(ip)) {
+                buf.push(ConfigCheck {
+                    from: from.clone(),
+                    to: Ident { ip, ..to.clone() },
+                    check: "udp_ping".to_string(),
+                    http_url: None,
+                    probe,
+                });
+            }
+        }
+
+        Ok(buf)
+    }
+
+    pub fn is_observer(&self) -> bool {
+        self.observer.is_some()
+    }
+
+    /// every address family both servers have in common results in its own path.
+    /// http checks do not depend on the address, they only run once per pair.
+    /// servers without the interface of the check are skipped.
+    fn check_target_ips(
+        &self,
+        check: &RawConfigCheck,
+        ip_version: IpVersion,
+        interface: &str,
+        from: &Ident,
+        to: &Ident,
+    ) -> Result<Vec<AllowIp>, ::anyhow::Error> {
+        if check.check == "http" {
+            return Ok(vec![to.ip.clone()]);
+        }
+
+        let from_ips = match self.get_server_by_identifier(&from.identifier) {
+            Some(s) => s.all_ips()?,
+            None => vec![from.ip.clone()],
+        };
+        let to_ips = match self.get_server_by_identifier(&to.identifier) {
+            Some(s) => match s.interface_ips(interface)? {
+                Some(ips) => ips,
+                None => return Ok(vec![]),
+            },
+            None => vec![to.ip.clone()],
+        };
+
+        Ok(to_ips
+            .into_iter()
+            .filter(|ip| ip_version.matches(ip))
+            .filter(|ip| from_ips.iter().any(|from_ip| from_ip.is_v6() == ip.is_v6()))
+            .collect())
+    }
+
+    /// udp ports the echo server has to listen on, the default port (or `[responder] ports`) and every port a check to this server uses.
+    pub fn listen_ports(&self) -> Result<BTreeSet<u16>, ::anyhow::Error> {
+        let mut ports = BTreeSet::new();
+        if self.is_observer() {
+            // an observer is not a target.
+            return Ok(ports);
+        }
+
+        match &self.responder.ports {
+            Some(p) => ports.extend(p.iter().copied()),
+            None => {
+                ports.insert(ConfigProbe::from_raw_config_probe(&RawConfigProbe::default(), &self.defaults).port);
+            }
+        };
+
+        for check in self.all_checks()? {
+            if check.check == "udp_ping" && check.to.identifier == self.get_self_identifier() {
+                ports.insert(check.probe.port);
+            }
+        }
+
+        Ok(ports)
+    }
+
+    /// labels results are aggregated by, see `aggregate_labels`.
+    pub fn get_aggregate_labels(&self) -> &[String] {
+        &self.aggregate_labels
+    }
+
+    /// `server:` and `group:` entries of `allow` are replaced by the addresses of the servers.
+    /// servers with a dns name that is not resolved yet are skipped.
+    pub fn responder_filter(&self) -> Result<ConfigResponderFilter, ::anyhow::Error> {
+        let allow = match &self.responder.allow {
+            None => None,
+            Some(allow) => {
+                let mut buf = vec![];
+                for allow in allow {
+                    buf.extend(self.resolve_allow_addr(AllowAddr::new_from_str(allow)?)?);
+                }
+                // the order of resolved addresses may change with every lookup, the filter should not.
+                buf.sort();
+                buf.dedup();
+                Some(buf)
+            }
+        };
+
+        Ok(ConfigResponderFilter {
+            allow,
+            rate_limit: self.responder.rate_limit,
+        })
+    }
+
+    fn resolve_allow_addr(&self, allow: AllowAddr) -> Result<Vec<AllowAddr>, ::anyhow::Error> {
+        let servers = match &allow {
+            AllowAddr::V4(..) | AllowAddr::V6(..) => return Ok(vec![allow]),
+            AllowAddr::Server(identifier, _) => self
+                .server
+                .iter()
+                .filter(|s| &s.identifier == identifier)
+                .take(1)
+                .collect::<Vec<_>>(),
+            AllowAddr::Group(name, _) => self.server.iter().filter(|s| s.groups.contains(name)).collect(),
+        };
+
+        let mut buf = vec![];
+        for server in servers.into_iter().filter(|s| s.is_resolved()) {
+            for ip in server.all_ips()? {
+                buf.push(match ip {
+                    AllowIp::V4(ip) => AllowAddr::V4(ip, allow.port().clone()),
+                    AllowIp::V6(ip) => AllowAddr::V6(ip, allow.port().clone()),
+                });
+            }
+        }
+
+        Ok(buf)
+    }
+
+    /// every address and port the echo server of this server listens on.
+    /// interfaces the server does not have are skipped, `validate` reports them.
+    pub fn listeners(&self) -> Result<Vec<ConfigListener>, ::anyhow::Error> {
+        let ports = self.listen_ports()?;
+
+        let ips = match (&self.responder.bind, &self.server_self) {
+            (Some(bind), Some(server_self)) => {
+                let mut ips = vec![];
+                for entry in bind {
+                    match AllowIp::new_from_str(entry) {
+                        Ok(ip) => ips.push(Some(ip.to_ip_addr())),
+                        Err(_) => ips.extend(
+                            server_self
+                                .interface_ips(entry)?
+                                .unwrap_or_default()
+                                .iter()
+                                .map(|ip| Some(ip.to_ip_addr())),
+                        ),
+                    }
+                }
+                ips
+            }
+            _ => vec![None],
+        };
+
+        let mut buf = vec![];
+        for ip in ips {
+            for port in ports.iter() {
+                let listener = ConfigListener { ip, port: *port };
+                if !buf.contains(&listener) {
+                    buf.push(listener);
+                }
+            }
+        }
+
+        Ok(buf)
+    }
+
+    /// every file and include directory the config was read from.
+    pub fn watched_paths(&self) -> &[PathBuf] {
+        &self.sources.paths
+    }
+
+    pub fn is_server_or_is_in_group(&self, server_or_group_identifier: &str) -> bool {
+        match &self.server_self {
+            None => false,
+            Some(server_self) => {
+                server_self.identifier == server_or_group_identifier
+                    || server_self
+                        .groups
+                        .contains(&server_or_group_identifier.to_string())
+            }
+        }
+    }
+
+    pub fn get_server_by_identifier(&self, identifier: &ServerIdentifier) -> Option<&ConfigServer> {
+        self.servers_by_identifier.get(identifier)
+    }
+
+    /// name of the server mehsh runs on, empty if the config was only parsed.
+    pub fn get_self_identifier(&self) -> &str {
+        self.self_server_identifier.as_deref().unwrap_or("")
+    }
+
+    /// `None` if the config was only parsed, see `Config::parse`.
+    pub fn get_server_self(&self) -> Option<&ConfigServer> {
+        self.server_self.as_ref()
+    }
+
+    /// servers selected by a server name, a group name or a selector, see `Selector`.
+    pub fn resolve_idents<I>(&self, raw_identifier: I) -> Result<Vec<Ident>, ::anyhow::Error>
+    where
+        I: AsRef<str> + Sized,
+    {
+        let identifier: &str = raw_identifier.as_ref();
+
+        let mut buf = vec![];
+        for s in self.select_servers(identifier)? {
+            // dns names are part of checks once they are resolved.
+            if !s.is_resolved() {
+                continue;
+            }
+
+            buf.push(Ident {
+                identifier: s.identifier.clone(),
+                interface: DEFAULT_INTERFACE.to_string(),
+                ip: AllowIp::new_from_str(&s.ip)?,
+            });
+        }
+
+        Ok(buf)
+    }
+
+    /// servers that have one of the addresses, on any interface.
+    pub fn servers_with_ips(&self, ips: &[IpAddr]) -> Vec<&ConfigServer> {
+        let mut buf = vec![];
+        let mut seen = HashSet::new();
+        for s in self.server.iter() {
+            if !seen.insert(&s.identifier) {
+                continue;
+            }
+
+            let server_ips = match s.all_ips() {
+                Ok(ips) => ips,
+                // invalid or unresolved addresses can not match.
+                Err(_) => continue,
+            };
+
+            if server_ips.iter().any(|ip| ips.contains(&ip.to_ip_addr())) {
+                buf.push(s);
+            }
+        }
+
+        buf
+    }
+
+    /// servers selected by a server name, a group name or a selector, in the order of the config.
+    pub fn select_servers(&self, selector: &str) -> Result<Vec<&ConfigServer>, ::anyhow::Error> {
+        if let Some(s) = self.servers_by_identifier.get(selector) {
+            return Ok(vec![s]);
+        }
+
+        let selector = Selector::parse(selector)?;
+
+        let mut buf = vec![];
+        let mut seen = HashSet::new();
+        for s in self.server.iter() {
+            // duplicate servers are reported by validate, the first one wins.
+            if !seen.insert(&s.identifier) {
+                continue;
+            }
+
+            let s = &self.servers_by_identifier[&s.identifier];
+            if selector.matches(s) {
+                buf.push(s);
+            }
+        }
+
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::allow_addr::AllowAddrPort;
+
+    fn load_config(content: &[u8]) -> Config {
+        Config::new_from_bytes("server1".to_string(), content).expect("could not load config")
+    }
+
+    #[test]
+    fn test_basic_servers() {
+        let c = load_config(
+            r#"
+[[group]]
+name = "g1"
+
+[[group]]
+name = "g2"
+
+[[server]]
+name = "server1"
+ip = "127.0.0.1"
+datacenter = "fra.dc11.foo.xyz"
+groups = ["g1"]
+
+[[server]]
+name = "server2"
+ip = "127.0.0.2"
+groups = ["g1", "g2"]
+        "#
+            .as_bytes(),
+        );
+
+        assert_eq!(vec!["server1", "server2"], {
+            let mut v = c
+                .resolve_idents("g1")
+                .unwrap()
+                .iter()
+                .map(|x| x.identifier.clone())
+                .collect::<Vec<_>>();
+            v.sort();
+            v
+        });
+
+        assert_eq!(
+            vec!["server2"],
+            c.resolve_idents("g2")
+                .unwrap()
+                .iter()
+                .map(|x| x.identifier.clone())
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            vec!["fra", "fra.dc11", "fra.dc11.foo", "fra.dc11.foo.xyz"],
+            c.get_server_by_identifier(&"server1".to_string())
+                .expect("server must exists")
+                .datacenter_as_entries
+        );
+    }
+
+    #[test]
+    fn test_ipv6_servers() {
+        let c = load_config(
+            r#"
+[[group]]
+name = "g1"
+
+[[server]]
+name = "server1"
+ip = "127.0.0.1"
+groups = ["g1"]
+
+[[server]]
+name = "server2"
+ip = "2a01:4f8::2"
+groups = ["g1"]
+
+[[server]]
+name = "server3"
+ip = "v6:2a01:4f8::3"
+groups = ["g1"]
+        "#
+            .as_bytes(),
+        );
+
+        let mut idents = c
+            .resolve_idents("g1")
+            .unwrap()
+            .iter()
+            .map(|x| (x.identifier.clone(), x.ip.clone()))
+            .collect::<Vec<_>>();
+        idents.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(
+            vec![
+                ("server1".to_string(), AllowIp::V4("127.0.0.1".parse().unwrap())),
+                ("server2".to_string(), AllowIp::V6("2a01:4f8::2".parse().unwrap())),
+                ("server3".to_string(), AllowIp::V6("2a01:4f8::3".parse().unwrap())),
+            ],
+            idents
+        );
+    }
+
+    #[test]
+    fn test_dual_stack_checks() {
+        let c = load_config(
+            r#"
+[[group]]
+name = "all"
+
+[[server]]
+name = "server1"
+ip = "127.0.0.1"
+ipv6 = "::1"
+groups = ["all"]
+
+[[server]]
+name = "server2"
+ip = "127.0.0.2"
+ipv6 = "2a01:4f8::2"
+groups = ["all"]
+
+[[server]]
+name = "server3"
+ip = "127.0.0.3"
+groups = ["all"]
+
+[[check]]
+from = "server1"
+to = "all"
+check = "udp_ping"
+
+[[check]]
+from = "server2"
+to = "all"
+check = "udp_ping"
+ip_version = "v6"
+        "#
+            .as_bytes(),
+        );
+
+        let mut checks = c
+            .all_checks()
+            .unwrap()
+            .iter()
+            .map(|c| format!("{} -> {} {}", c.from.identifier, c.to.identifier, c.to.ip.to_string()))
+            .collect::<Vec<_>>();
+        checks.sort();
+
+        assert_eq!(
+            vec![
+                "server1 -> server1 127.0.0.1",
+                "server1 -> server1 ::1",
+                "server1 -> server2 127.0.0.2",
+                "server1 -> server2 2a01:4f8::2",
+                "server1 -> server3 127.0.0.3",
+                "server2 -> server1 ::1",
+                "server2 -> server2 2a01:4f8::2",
+            ],
+            checks
+        );
+    }
+
+    #[test]
+    fn test_interfaces() {
+        let c = load_config(
+            r#"
+[[group]]
+name = "all"
+
+[[server]]
+name = "server1"
+ip = "127.0.0.1"
+interfaces = { vpn = "10.0.0.1", public = ["1.2.3.1", "2a01:4f8::1"] }
+groups = ["all"]
+
+[[server]]
+name = "server2"
+ip = "127.0.0.2"
+interfaces = { vpn = "10.0.0.2" }
+groups = ["all"]
+
+[[server]]
+name = "server3"
+ip = "127.0.0.3"
+groups = ["all"]
+
+[[check]]
+from = "server1"
+to = "all"
+check = "udp_ping"
+interface = "vpn"
+
+[[check]]
+from = "server1"
+to = "all"
+check = "udp_ping"
+interface = "public"
+        "#
+            .as_bytes(),
+        );
+
+        let mut checks = c
+            .all_checks()
+            .unwrap()
+            .iter()
+            .map(|c| format!("{} -> {} {} {}", c.from.identifier, c.to.identifier, c.to.interface, c.to.ip.to_string()))
+            .collect::<Vec<_>>();
+        checks.sort();
+
+        assert_eq!(
+            vec![
+                "server1 -> server1 public 1.2.3.1",
+                "server1 -> server1 public 2a01:4f8::1",
+                "server1 -> server1 vpn 10.0.0.1",
+                "server1 -> server2 vpn 10.0.0.2",
+            ],
+            checks
+        );
+    }
+
+    #[test]
+    fn test_probe_settings() {
+        let c = load_config(
+            r#"
+[defaults]
+interval_ms = 100
+window_ms = 10000
+
+[[server]]
+name = "server1"
+ip = "127.0.0.1"
+groups = []
+
+[[server]]
+name = "server2"
+ip = "127.0.0.2"
+groups = []
+
+[[check]]
+from = "server2"
+to = "server1"
+check = "udp_ping"
+
+[[check]]
+from = "server1"
+to = "server2"
+check = "udp_ping"
+port = 5000
+timeout_ms = 2000
+        "#
+            .as_bytes(),
+        );
+
+        let mut checks = c.all_checks().unwrap();
+        checks.sort_by(|a, b| a.to.identifier.cmp(&b.to.identifier));
+
+        assert_eq!(
+            ConfigProbe {
+                interval: Duration::from_millis(100),
+                port: 4232,
+                timeout: Duration::from_millis(1000),
+                window: Duration::from_millis(10000),
+            },
+            checks[0].probe
+        );
+        assert_eq!(
+            ConfigProbe {
+                interval: Duration::from_millis(100),
+                port: 5000,
+                timeout: Duration::from_millis(2000),
+                window: Duration::from_millis(10000),
+            },
+            checks[1].probe
+        );
+
+        // server1 answers checks on the default port only.
+        assert_eq!(vec![4232], c.listen_ports().unwrap().into_iter().collect::<Vec<_>>());
+
+        let c = load_config(
+            r#"
+[[server]]
+name = "server1"
+ip = "127.0.0.1"
+groups = []
+
+[[check]]
+from = "server1"
+to = "server1"
+check = "udp_ping"
+
+[[check]]
+from = "server1"
+to = "server1"
+check = "udp_ping"
+port = 5000
+        "#
+            .as_bytes(),
+        );
+
+        let mut ports = c.all_checks().unwrap().iter().map(|c| c.probe.port).collect::<Vec<_>>();
+        ports.sort();
+        assert_eq!(vec![4232, 5000], ports);
+
+        let c = Config::parse(b"[defaults]\ninterval_ms = 0\n").expect("could not parse config");
+        assert_eq!(
+            vec!["defaults.interval_ms: interval_ms must be greater than 0"],
+            c.validate().iter().map(|e| e.to_string()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let c = Config::parse(
+            r#"
+aggregate_labels = ["provider"]
+
+[[server]]
+name = "server1"
+ip = "127.0.0.1"
+groups = []
+labels = { provider = "hetzner", rack = "r12" }
+extra1 = "foo"
+
+[[server]]
+name = "server2"
+ip = "127.0.0.2"
+groups = []
+labels = { "public ip" = "1.2.3.4", extra1 = "bar" }
+extra1 = "foo"
+        "#
+            .as_bytes(),
+        )
+        .expect("could not parse config");
+
+        let labels = |name: &str| {
+            c.get_server_by_identifier(&name.to_string())
+                .expect("server must exists")
+                .labels
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec!["extra1=foo", "provider=hetzner", "rack=r12"], labels("server1"));
+        // labels win over the deprecated extra fields.
+        assert_eq!(vec!["extra1=bar", "public ip=1.2.3.4"], labels("server2"));
+        assert_eq!(vec!["provider"], c.get_aggregate_labels());
+
+        assert!(c
+            .validate()
+            .iter()
+            .any(|e| e.to_string() == "server[1].labels.public ip: label name public ip may only contain a-z, A-Z, 0-9, _ and -"));
+    }
+
+    #[test]
+    fn test_selectors() {
+        let c = Config::parse(
+            r#"
+[[group]]
+name = "databaseservers"
+
+[[group]]
+name = "web"
+
+[[server]]
+name = "db1"
+ip = "127.0.0.1"
+datacenter = "fra.dc1"
+groups = ["databaseservers"]
+labels = { provider = "hetzner" }
+
+[[server]]
+name = "db-backup"
 ip = "127.0.0.2"
-groups = ["g1", "g2"]
+datacenter = "nbg.dc1"
+groups = ["databaseservers"]
+
+[[server]]
+name = "web1"
+ip = "127.0.0.3"
+datacenter = "fra.dc2"
+groups = ["web"]
+labels = { provider = "hetzner" }
+
+[[check]]
+from = "web"
+to = "databaseservers - db-backup"
+check = "udp_ping"
+
+[[check]]
+from = "group:web & dc:fra"
+to = "nope | provider=hetz*"
+check = "udp_ping"
+
+[[check]]
+from = "web &"
+to = "web"
+check = "udp_ping"
         "#
             .as_bytes(),
+        )
+        .expect("could not parse config");
+
+        let resolve = |selector: &str| {
+            c.resolve_idents(selector)
+                .unwrap()
+                .into_iter()
+                .map(|i| i.identifier)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec!["db1"], resolve("databaseservers - db-backup"));
+        assert_eq!(vec!["db1", "web1"], resolve("provider=hetzner"));
+        assert_eq!(vec!["web1"], resolve("group:web & dc:fra"));
+        assert_eq!(vec!["db1", "db-backup", "web1"], resolve("dc:fra.* | server:db*"));
+        assert_eq!(vec!["db-backup"], resolve("db-backup"));
+
+        assert_eq!(
+            vec![
+                "check[1].to: nope is neither a server nor a group",
+                "check[2].from: invalid selector 'web &': unexpected end",
+                "server[1].name: server db-backup is not part of any check",
+            ],
+            c.validate()
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
         );
 
-        assert_eq!(vec!["server1", "server2"], {
-            let mut v = c
-                .resolve_idents("g1")
+        assert_eq!(
+            ("check[0].to".to_string(), vec!["db1".to_string()]),
+            c.selector_expansions()
+                .into_iter()
+                .map(|(location, _, servers)| (location.to_string(), servers))
+                .nth(1)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_nested_groups() {
+        let c = Config::parse(
+            r#"
+[[group]]
+name = "applicationservers"
+
+[[group]]
+name = "databaseservers"
+
+[[group]]
+name = "backend"
+include_groups = ["applicationservers", "databaseservers"]
+
+[[group]]
+name = "all"
+include_groups = ["backend", "loadbalancers"]
+
+[[group]]
+name = "a"
+include_groups = ["b"]
+
+[[group]]
+name = "b"
+include_groups = ["a"]
+
+[[server]]
+name = "app1"
+ip = "127.0.0.1"
+groups = ["applicationservers"]
+
+[[server]]
+name = "db1"
+ip = "127.0.0.2"
+groups = ["databaseservers", "a"]
+
+[[check]]
+from = "all"
+to = "backend"
+check = "udp_ping"
+        "#
+            .as_bytes(),
+        )
+        .expect("could not parse config");
+
+        let resolve = |selector: &str| {
+            c.resolve_idents(selector)
                 .unwrap()
+                .into_iter()
+                .map(|i| i.identifier)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec!["app1", "db1"], resolve("backend"));
+        assert_eq!(vec!["app1", "db1"], resolve("all"));
+        assert_eq!(vec!["db1"], resolve("b"));
+
+        assert_eq!(
+            vec![
+                "group[3].include_groups: group loadbalancers is not defined",
+                "group[5].include_groups: groups include each other: a -> b -> a",
+            ],
+            c.validate()
                 .iter()
-                .map(|x| x.identifier.clone())
-                .collect::<Vec<_>>();
-            v.sort();
-            v
-        });
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_hostnames() {
+        assert!(is_hostname("db1.example.com"));
+        assert!(is_hostname("localhost"));
+        assert!(!is_hostname("127.0.0.300"));
+        assert!(!is_hostname("-foo.example.com"));
+        assert!(!is_hostname("foo_bar"));
+
+        let c = load_config(
+            r#"
+[[server]]
+name = "server1"
+ip = "127.0.0.1"
+groups = []
+
+[[server]]
+name = "server2"
+ip = "db.example.com"
+groups = []
+
+[[check]]
+from = "server1"
+to = "server2"
+check = "udp_ping"
+        "#
+            .as_bytes(),
+        );
+
+        assert!(c.validate().is_empty());
+        assert_eq!(vec!["db.example.com"], c.hostnames().into_iter().collect::<Vec<_>>());
+        // not resolved yet.
+        assert!(c.all_checks().unwrap().is_empty());
+
+        let mut resolved = HashMap::new();
+        resolved.insert(
+            "db.example.com".to_string(),
+            vec!["10.0.0.2".parse().unwrap(), "2a01:4f8::2".parse().unwrap()],
+        );
+
+        let mut checks = c
+            .with_resolved_hostnames(&resolved)
+            .all_checks()
+            .unwrap()
+            .iter()
+            .map(|c| c.to.ip.to_string())
+            .collect::<Vec<_>>();
+        checks.sort();
+
+        // server1 has no ipv6 address.
+        assert_eq!(vec!["10.0.0.2"], checks);
+    }
+
+    #[test]
+    fn test_servers_with_ips() {
+        let c = Config::parse(
+            r#"
+[[server]]
+name = "server1"
+ip = "10.0.0.1"
+groups = []
+
+[[server]]
+name = "server2"
+ip = "10.0.0.2"
+ipv6 = "2a01:4f8::2"
+interfaces = { vpn = "192.168.0.2" }
+groups = []
+        "#
+            .as_bytes(),
+        )
+        .expect("could not parse config");
+
+        let names = |ips: &[&str]| {
+            c.servers_with_ips(&ips.iter().map(|ip| ip.parse().unwrap()).collect::<Vec<_>>())
+                .iter()
+                .map(|s| s.identifier.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec!["server2"], names(&["127.0.0.1", "192.168.0.2"]));
+        assert_eq!(vec!["server2"], names(&["2a01:4f8::2"]));
+        assert_eq!(vec!["server1", "server2"], names(&["10.0.0.1", "10.0.0.2"]));
+        assert!(names(&["127.0.0.1"]).is_empty());
+    }
+
+    #[test]
+    fn test_observer() {
+        let path = ::std::env::temp_dir().join(format!("mehsh_observer_{}.toml", ::std::process::id()));
+        ::std::fs::write(
+            &path,
+            r#"
+[[group]]
+name = "all"
+
+[[server]]
+name = "server1"
+ip = "127.0.0.1"
+ipv6 = "::1"
+groups = ["all"]
+
+[[server]]
+name = "server2"
+ip = "127.0.0.2"
+groups = ["all"]
+
+[[check]]
+from = "all"
+to = "all"
+check = "udp_ping"
+        "#,
+        )
+        .unwrap();
+
+        let observer = |to: &str, ip_version| ConfigObserver {
+            to: to.to_string(),
+            ip_version,
+        };
+
+        let c = Config::new_observer_from_file("office".to_string(), observer("all", IpVersion::Both), path.clone())
+            .expect("could not load config");
+
+        let mut checks = c
+            .self_checks()
+            .unwrap()
+            .iter()
+            .map(|c| format!("{} -> {} {}", c.from.identifier, c.to.identifier, c.to.ip.to_string()))
+            .collect::<Vec<_>>();
+        checks.sort();
 
         assert_eq!(
-            vec!["server2"],
-            c.resolve_idents("g2")
+            vec!["office -> server1 127.0.0.1", "office -> server1 ::1", "office -> server2 127.0.0.2"],
+            checks
+        );
+        assert!(c.listen_ports().unwrap().is_empty());
+        assert!(c.get_server_self().is_none());
+
+        let c = Config::new_observer_from_file("office".to_string(), observer("all - server2", IpVersion::V6), path.clone())
+            .expect("could not load config");
+        assert_eq!(1, c.self_checks().unwrap().len());
+
+        assert!(Config::new_observer_from_file("server1".to_string(), observer("all", IpVersion::Both), path.clone()).is_err());
+        assert!(Config::new_observer_from_file("office".to_string(), observer("all &", IpVersion::Both), path.clone()).is_err());
+
+        ::std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_responder() {
+        let config = r#"
+[responder]
+bind = ["vpn", "127.0.0.1"]
+ports = [4232, 5000]
+
+[[server]]
+name = "server1"
+ip = "10.0.0.1"
+interfaces = { vpn = ["192.168.0.1", "fd00::1"] }
+groups = []
+
+[[server]]
+name = "server2"
+ip = "10.0.0.2"
+groups = []
+
+[[check]]
+from = "server2"
+to = "server1"
+check = "udp_ping"
+port = 6000
+        "#;
+
+        let listeners = |name: &str| {
+            Config::new_from_bytes(name.to_string(), config.as_bytes())
+                .expect("could not load config")
+                .listeners()
                 .unwrap()
                 .iter()
-                .map(|x| x.identifier.clone())
+                .map(|l| l.to_string())
                 .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec![
+                "192.168.0.1:4232",
+                "192.168.0.1:5000",
+                "192.168.0.1:6000",
+                "[fd00::1]:4232",
+                "[fd00::1]:5000",
+                "[fd00::1]:6000",
+                "127.0.0.1:4232",
+                "127.0.0.1:5000",
+                "127.0.0.1:6000",
+            ],
+            listeners("server1")
         );
+        // server2 has no vpn interface.
+        assert_eq!(vec!["127.0.0.1:4232", "127.0.0.1:5000"], listeners("server2"));
 
         assert_eq!(
-            vec!["fra", "fra.dc11", "fra.dc11.foo", "fra.dc11.foo.xyz"],
-            c.get_server_by_identifier(&"server1".to_string())
-                .expect("server must exists")
-                .datacenter_as_entries
+            vec!["responder.bind: server server2 (server[1]) has no interface vpn, its responder does not listen on it"],
+            Config::parse(config.as_bytes())
+                .unwrap()
+                .validate()
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_responder_allow() {
+        let config = r#"
+[responder]
+allow = ["server:server1:1000-2000", "group:web", "v6:[fd00::9]:4232"]
+
+[[group]]
+name = "web"
+
+[[server]]
+name = "server1"
+ip = "10.0.0.1"
+ipv6 = "fd00::1"
+groups = []
+
+[[server]]
+name = "server2"
+ip = "10.0.0.2"
+interfaces = { vpn = ["192.168.0.2"] }
+groups = ["web"]
+
+[[server]]
+name = "server3"
+ip = "server3.example.com"
+groups = ["web"]
+
+[[check]]
+from = "server1"
+to = "server2"
+check = "udp_ping"
+        "#;
+
+        let config_content = config;
+        let config = Config::new_from_bytes("server1".to_string(), config.as_bytes()).expect("could not load config");
+        let filter = config.responder_filter().unwrap();
+
+        // server3 is not resolved yet.
+        assert_eq!(
+            Some(vec![
+                AllowAddr::V4("10.0.0.1".parse().unwrap(), AllowAddrPort::Range(1000, 2000)),
+                AllowAddr::V4("10.0.0.2".parse().unwrap(), AllowAddrPort::AnyPort),
+                AllowAddr::V4("192.168.0.2".parse().unwrap(), AllowAddrPort::AnyPort),
+                AllowAddr::V6("fd00::1".parse().unwrap(), AllowAddrPort::Range(1000, 2000)),
+                AllowAddr::V6("fd00::9".parse().unwrap(), AllowAddrPort::Port(4232)),
+            ]),
+            filter.allow.clone()
+        );
+
+        let resolved = config.with_resolved_hostnames(&HashMap::from([(
+            "server3.example.com".to_string(),
+            vec!["10.0.0.3".parse().unwrap()],
+        )]));
+        assert!(resolved
+            .responder_filter()
+            .unwrap()
+            .allow
+            .unwrap()
+            .contains(&AllowAddr::V4("10.0.0.3".parse().unwrap(), AllowAddrPort::AnyPort)));
+
+        // the same addresses in another order do not change the filter.
+        let reordered = config_content.replace(
+            r#"allow = ["server:server1:1000-2000", "group:web", "v6:[fd00::9]:4232"]"#,
+            r#"allow = ["v6:[fd00::9]:4232", "group:web", "server:server2", "server:server1:1000-2000"]"#,
+        );
+        let reordered = Config::new_from_bytes("server1".to_string(), reordered.as_bytes()).expect("could not load config");
+        assert_eq!(filter, reordered.responder_filter().unwrap());
+
+        let config = r#"
+[responder]
+allow = ["server:unknown", "group:unknown", "10.0.0.1"]
+
+[[server]]
+name = "server1"
+ip = "10.0.0.1"
+groups = []
+        "#;
+
+        assert_eq!(
+            vec![
+                "responder.allow: unknown is neither a server nor a group",
+                "responder.allow: group unknown is not defined",
+                "responder.allow: could not parse '10.0.0.1' the identifier should start with 'v4:', 'v6:', 'server:' or 'group:'",
+                "server[0].name: server server1 is not part of any check",
+            ],
+            Config::parse(config.as_bytes())
+                .unwrap()
+                .validate()
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_analysis() {
+        let c = load_config(
+            r#"
+[[server]]
+name = "server1"
+ip = "127.0.0.1"
+groups = []
+
+[[server]]
+name = "server2"
+ip = "127.0.0.2"
+groups = []
+
+[[check]]
+from = "server1"
+to = "server2"
+check = "udp_ping"
+
+[[analysis]]
+from = "server1"
+to = "server2"
+name = "mtr"
+min_loss_percent = 2.5
+min_loss_percent_average = "5m"
+min_loss_duration_ms = 30000
+command = "mtr {{server.to.ip}}"
+        "#
+            .as_bytes(),
+        );
+
+        let analysis = c.all_analyisis().unwrap();
+        assert_eq!(1, analysis.len());
+        assert_eq!(None, analysis[0].min_loss);
+        assert_eq!(Some(250), analysis[0].min_loss_basis_points);
+        assert_eq!(LossAverage::FiveMinutes, analysis[0].min_loss_percent_average);
+        assert_eq!(Duration::from_secs(30), analysis[0].min_loss_duration);
+
+        let c = Config::parse(
+            r#"
+[[analysis]]
+from = "server1"
+to = "server1"
+name = "a"
+command = "ls"
+
+[[analysis]]
+from = "server1"
+to = "server1"
+name = "b"
+min_loss_percent = 150.0
+command = "ls"
+        "#
+            .as_bytes(),
+        )
+        .expect("could not parse config");
+
+        assert_eq!(
+            vec![
+                "analysis[0].min_loss: analysis needs min_loss or min_loss_percent",
+                "analysis[1].min_loss_percent: min_loss_percent must be between 0 and 100, got 150",
+            ],
+            c.validate()
+                .iter()
+                .map(|e| e.to_string())
+                .filter(|e| e.contains("min_loss"))
+                .collect::<Vec<_>>()
         );
     }
+
+    #[test]
+    fn test_validate() {
+        let c = Config::parse(
+            r#"
+[[group]]
+name = "g1"
+
+[[group]]
+name = "g1"
+
+[[server]]
+name = "server1"
+ip = "127.0.0.1"
+groups = ["g1", "g2"]
+
+[[server]]
+name = "server1"
+ip = "127.0.0.300"
+groups = []
+
+[[server]]
+name = "server2"
+ip = "127.0.0.2"
+ipv6 = "127.0.0.3"
+groups = []
+
+[[check]]
+from = "g1"
+to = "g3"
+check = "udp_ping"
+
+[[check]]
+from = "g1"
+to = "g1"
+check = "http"
+
+[[check]]
+from = "g1"
+to = "g1"
+check = "tcp_ping"
+        "#
+            .as_bytes(),
+        )
+        .expect("could not parse config");
+
+        assert_eq!(
+            vec![
+                "group[1].name: group g1 already registered at group[0]",
+                "server[0].groups: group g2 is not defined",
+                "server[1].name: server server1 already registered at server[0]",
+                "server[1].ip: could not parse ip address '127.0.0.300'",
+                "server[2].ipv6: '127.0.0.3' is not an ipv6 address",
+                "check[0].to: g3 is neither a server nor a group",
+                "check[1].http_url: http check needs an http_url",
+                "check[2].check: unknown check type tcp_ping",
+                "server[2].name: server server2 is not part of any check",
+            ],
+            c.validate()
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        );
+
+        let err = Config::new_from_bytes("server1".to_string(), b"[[server]]\nname = 1")
+            .expect_err("config must be invalid");
+        assert!(err.to_string().starts_with("could not parse config"));
+    }
 }
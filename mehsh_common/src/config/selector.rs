@@ -0,0 +1,211 @@
+use crate::config::ConfigServer;
+use anyhow::{anyhow, Context};
+use glob::Pattern;
+
+/// selects servers in `from` / `to` of checks and analysis.
+///
+/// - `name` a server or a group
+/// - `server:glob`, `group:glob`, `dc:glob` servers by name, group or datacenter
+/// - `label=glob` servers with a label
+/// - `a | b` union, `a & b` intersection, `a - b` difference, `( ... )`
+///
+/// `&` binds stronger than `|` and `-`, `-` must be surrounded by spaces because it is valid in names.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector {
+    Name(String),
+    Server(Pattern),
+    Group(Pattern),
+    Datacenter(Pattern),
+    Label(String, Pattern),
+    Union(Box<Selector>, Box<Selector>),
+    Intersection(Box<Selector>, Box<Selector>),
+    Difference(Box<Selector>, Box<Selector>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Union,
+    Intersection,
+    Difference,
+    Open,
+    Close,
+}
+
+impl Selector {
+    pub fn parse(raw: &str) -> Result<Self, ::anyhow::Error> {
+        let tokens = Self::tokenize(raw);
+        let mut pos = 0;
+
+        let selector = Self::parse_union(&tokens, &mut pos).with_context(|| format!("invalid selector '{}'", raw))?;
+        if pos != tokens.len() {
+            return Err(anyhow!("invalid selector '{}', unexpected {:?}", raw, tokens[pos]));
+        }
+
+        Ok(selector)
+    }
+
+    fn tokenize(raw: &str) -> Vec<Token> {
+        let mut tokens = vec![];
+        let mut word = String::new();
+
+        let push_word = |word: &mut String, tokens: &mut Vec<Token>| {
+            match word.as_str() {
+                "" => {}
+                "-" => tokens.push(Token::Difference),
+                _ => tokens.push(Token::Word(word.clone())),
+            };
+            word.clear();
+        };
+
+        for c in raw.chars() {
+            let token = match c {
+                '|' => Some(Token::Union),
+                '&' => Some(Token::Intersection),
+                '(' => Some(Token::Open),
+                ')' => Some(Token::Close),
+                c if c.is_whitespace() => None,
+                c => {
+                    word.push(c);
+                    continue;
+                }
+            };
+
+            push_word(&mut word, &mut tokens);
+            tokens.extend(token);
+        }
+
+        push_word(&mut word, &mut tokens);
+
+        tokens
+    }
+
+    fn parse_union(tokens: &[Token], pos: &mut usize) -> Result<Self, ::anyhow::Error> {
+        let mut left = Self::parse_intersection(tokens, pos)?;
+
+        loop {
+            match tokens.get(*pos) {
+                Some(Token::Union) => {
+                    *pos += 1;
+                    left = Selector::Union(Box::new(left), Box::new(Self::parse_intersection(tokens, pos)?));
+                }
+                Some(Token::Difference) => {
+                    *pos += 1;
+                    left = Selector::Difference(Box::new(left), Box::new(Self::parse_intersection(tokens, pos)?));
+                }
+                _ => return Ok(left),
+            }
+        }
+    }
+
+    fn parse_intersection(tokens: &[Token], pos: &mut usize) -> Result<Self, ::anyhow::Error> {
+        let mut left = Self::parse_atom(tokens, pos)?;
+
+        while let Some(Token::Intersection) = tokens.get(*pos) {
+            *pos += 1;
+            left = Selector::Intersection(Box::new(left), Box::new(Self::parse_atom(tokens, pos)?));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_atom(tokens: &[Token], pos: &mut usize) -> Result<Self, ::anyhow::Error> {
+        let token = tokens.get(*pos).ok_or_else(|| anyhow!("unexpected end"))?;
+        *pos += 1;
+
+        match token {
+            Token::Open => {
+                let selector = Self::parse_union(tokens, pos)?;
+                match tokens.get(*pos) {
+                    Some(Token::Close) => {
+                        *pos += 1;
+                        Ok(selector)
+                    }
+                    _ => Err(anyhow!("missing )")),
+                }
+            }
+            Token::Word(word) => Self::parse_matcher(word),
+            other => Err(anyhow!("unexpected {:?}", other)),
+        }
+    }
+
+    fn parse_matcher(word: &str) -> Result<Self, ::anyhow::Error> {
+        let pattern = |p: &str| Pattern::new(p).with_context(|| format!("invalid pattern '{}'", p));
+
+        if let Some((label, value)) = word.split_once('=') {
+            return Ok(Selector::Label(label.to_string(), pattern(value)?));
+        }
+
+        match word.split_once(':') {
+            Some(("server", p)) => Ok(Selector::Server(pattern(p)?)),
+            Some(("group", p)) => Ok(Selector::Group(pattern(p)?)),
+            Some(("dc", p)) => Ok(Selector::Datacenter(pattern(p)?)),
+            Some((kind, _)) => Err(anyhow!("unknown selector {}:", kind)),
+            None => Ok(Selector::Name(word.to_string())),
+        }
+    }
+
+    pub fn matches(&self, server: &ConfigServer) -> bool {
+        match self {
+            Selector::Name(name) => &server.identifier == name || server.groups.contains(name),
+            Selector::Server(p) => p.matches(&server.identifier),
+            Selector::Group(p) => server.groups.iter().any(|g| p.matches(g)),
+            Selector::Datacenter(p) => server.datacenter_as_entries.iter().any(|dc| p.matches(dc)),
+            Selector::Label(label, p) => server.labels.get(label).map(|v| p.matches(v)).unwrap_or(false),
+            Selector::Union(a, b) => a.matches(server) || b.matches(server),
+            Selector::Intersection(a, b) => a.matches(server) && b.matches(server),
+            Selector::Difference(a, b) => a.matches(server) && !b.matches(server),
+        }
+    }
+
+    /// plain server or group names, they must exist.
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            Selector::Name(name) => vec![name.as_str()],
+            Selector::Union(a, b) | Selector::Intersection(a, b) | Selector::Difference(a, b) => {
+                let mut names = a.names();
+                names.extend(b.names());
+                names
+            }
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let name = |n: &str| Box::new(Selector::Name(n.to_string()));
+
+        assert_eq!(
+            Selector::Difference(name("databaseservers"), name("database_backup")),
+            Selector::parse("databaseservers - database_backup").unwrap()
+        );
+        assert_eq!(Selector::Name("db-1".to_string()), Selector::parse("db-1").unwrap());
+        assert_eq!(
+            Selector::Union(
+                name("a"),
+                Box::new(Selector::Intersection(
+                    Box::new(Selector::Group(Pattern::new("web").unwrap())),
+                    Box::new(Selector::Datacenter(Pattern::new("fra.*").unwrap())),
+                ))
+            ),
+            Selector::parse("a | group:web & dc:fra.*").unwrap()
+        );
+        assert_eq!(
+            Selector::Intersection(
+                Box::new(Selector::Union(name("a"), name("b"))),
+                Box::new(Selector::Label("provider".to_string(), Pattern::new("hetzner").unwrap())),
+            ),
+            Selector::parse("(a | b) & provider=hetzner").unwrap()
+        );
+
+        assert!(Selector::parse("a |").is_err());
+        assert!(Selector::parse("(a | b").is_err());
+        assert!(Selector::parse("a b").is_err());
+        assert!(Selector::parse("foo:bar").is_err());
+    }
+}